@@ -1,21 +1,79 @@
-use std::io::{self, Read};
+use std::{
+    env,
+    fmt::{self, Display},
+    io::{self, Read, Write},
+    thread,
+    time::Duration,
+};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+
+const DEFAULT_REPLAY_DELAY_MS: u64 = 200;
 
 fn main() -> Result<()> {
+    let mode = Mode::from_args(env::args().skip(1))?;
+
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
     let input = input.trim();
 
-    let polymer = part1(input);
-    println!("Part 1 answer: {}", polymer.len());
+    match mode {
+        Mode::Solve => {
+            let polymer = part1(input);
+            println!("Part 1 answer: {}", polymer.len());
 
-    let polymer = part2(input);
-    println!("Part 2 answer: {}", polymer.len());
+            let polymer = part2(input);
+            println!("Part 2 answer: {}", polymer.len());
+        }
+        Mode::Trace => {
+            let (polymer, trace) = react_traced(input);
+            for annihilation in &trace {
+                println!("{}", annihilation);
+            }
+            println!("Reacted polymer has length: {}", polymer.len());
+        }
+        Mode::Replay(delay) => {
+            let (_, trace) = react_traced(input);
+            replay(input, &trace, delay)?;
+        }
+    }
 
     Ok(())
 }
 
+/// Selects what the program does with the polymer read from stdin.
+enum Mode {
+    /// Print the answers to both parts of the puzzle.
+    Solve,
+    /// Print a compact log of every annihilation that occurs while reacting.
+    Trace,
+    /// Animate the reaction in the terminal, pausing between each annihilation.
+    Replay(Duration),
+}
+
+impl Mode {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Mode> {
+        let mode = match args.next().as_deref() {
+            None => Mode::Solve,
+            Some("--trace") => Mode::Trace,
+            Some("--replay") => {
+                let delay_ms = match args.next() {
+                    Some(ms) => ms.parse()?,
+                    None => DEFAULT_REPLAY_DELAY_MS,
+                };
+                Mode::Replay(Duration::from_millis(delay_ms))
+            }
+            Some(arg) => bail!("Usage: day05 [--trace | --replay [DELAY_MS]] (got {})", arg),
+        };
+
+        if let Some(arg) = args.next() {
+            bail!("Unexpected argument: {}", arg);
+        }
+
+        Ok(mode)
+    }
+}
+
 fn part1(polymer: &str) -> String {
     react(polymer)
 }
@@ -43,21 +101,63 @@ fn part2(polymer: &str) -> String {
     min.unwrap()
 }
 
+/// A single reaction between two adjacent units of opposite polarity.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Annihilation {
+    /// The reaction pass (starting at 1) during which the units were destroyed.
+    pass: usize,
+    /// The positions of the two units within the original polymer.
+    positions: (usize, usize),
+    /// The two units that were destroyed.
+    units: (char, char),
+}
+
+impl Display for Annihilation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}-{} {}{}",
+            self.pass, self.positions.0, self.positions.1, self.units.0, self.units.1
+        )
+    }
+}
+
 fn react(polymer: &str) -> String {
-    let mut polymer: Vec<char> = polymer.chars().collect();
+    react_with(polymer, |_| {})
+}
+
+fn react_traced(polymer: &str) -> (String, Vec<Annihilation>) {
+    let mut trace = vec![];
+    let reacted = react_with(polymer, |a| trace.push(a));
+    (reacted, trace)
+}
+
+fn react_with<F>(polymer: &str, mut on_annihilation: F) -> String
+where
+    F: FnMut(Annihilation),
+{
+    // Each unit is paired with its position in the original polymer so that the
+    // annihilations can be reported against the input rather than the current pass.
+    let mut polymer: Vec<(usize, char)> = polymer.chars().enumerate().collect();
     let mut reacted = vec![];
+    let mut pass = 1;
 
     loop {
         let mut shrunk = false;
         let mut i = 1;
         while i < polymer.len() {
-            let p = polymer[i - 1];
-            let c = polymer[i];
-            if p != c && p.to_ascii_uppercase() == c.to_ascii_uppercase() {
+            let (pi, p) = polymer[i - 1];
+            let (ci, c) = polymer[i];
+            if p != c && p.eq_ignore_ascii_case(&c) {
+                on_annihilation(Annihilation {
+                    pass,
+                    positions: (pi, ci),
+                    units: (p, c),
+                });
                 shrunk = true;
                 i += 2;
             } else {
-                reacted.push(p);
+                reacted.push((pi, p));
                 i += 1;
             }
         }
@@ -72,7 +172,64 @@ fn react(polymer: &str) -> String {
 
         std::mem::swap(&mut polymer, &mut reacted);
         reacted.clear();
+        pass += 1;
     }
 
-    reacted.iter().collect()
+    reacted.iter().map(|(_, c)| c).collect()
+}
+
+/// Animates the collapse of `polymer` by redrawing it after each annihilation in `trace`.
+fn replay(polymer: &str, trace: &[Annihilation], delay: Duration) -> Result<()> {
+    const CLEAR: &str = "\x1b[2J\x1b[H";
+    const HIGHLIGHT: &str = "\x1b[1;31m";
+    const RESET: &str = "\x1b[0m";
+
+    let units: Vec<char> = polymer.chars().collect();
+    let mut alive = vec![true; units.len()];
+    let mut stdout = io::stdout();
+
+    for (step, annihilation) in trace.iter().enumerate() {
+        let (left, right) = annihilation.positions;
+        let mut frame = String::new();
+        for (i, unit) in units.iter().enumerate().filter(|(i, _)| alive[*i]) {
+            if i == left || i == right {
+                frame.push_str(HIGHLIGHT);
+                frame.push(*unit);
+                frame.push_str(RESET);
+            } else {
+                frame.push(*unit);
+            }
+        }
+
+        write!(
+            stdout,
+            "{}Step {}/{} (pass {})\n{}\n",
+            CLEAR,
+            step + 1,
+            trace.len(),
+            annihilation.pass,
+            frame
+        )?;
+        stdout.flush()?;
+        thread::sleep(delay);
+
+        alive[left] = false;
+        alive[right] = false;
+    }
+
+    let remaining: String = units
+        .iter()
+        .zip(alive)
+        .filter(|(_, alive)| *alive)
+        .map(|(unit, _)| unit)
+        .collect();
+    writeln!(
+        stdout,
+        "{}Reacted polymer ({} units):\n{}",
+        CLEAR,
+        remaining.len(),
+        remaining
+    )?;
+
+    Ok(())
 }
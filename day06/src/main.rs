@@ -1,68 +1,137 @@
-#![feature(int_abs_diff)]
-
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::{
     cmp,
-    collections::{HashMap, HashSet},
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    env,
+    fmt::Write,
+    fs,
     io::{self, Read},
-    iter,
     str::FromStr,
 };
 
+/// The total distance to all markers below which a cell is considered safe, unless given.
+const DEFAULT_MAX_TOTAL_DIST: u64 = 10000;
+
+/// The number of pixels along each side of a cell in rendered images.
+const PPM_SCALE: usize = 4;
+
 fn main() -> Result<()> {
+    let options = Options::from_args(env::args().skip(1))?;
+
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
 
     let markers = read_markers(&input)?;
 
-    let answer = part1(&markers)?;
+    let (closest_grid, infinite_markers) =
+        build_area_grid(&markers, options.metric.as_ref(), options.margin)?;
+    let answer = part1(&closest_grid, &infinite_markers);
     println!("Answer to part 1: {}", answer);
 
-    let answer = part2(&markers, 10000)?;
+    let answer = part2(&markers, options.metric.as_ref(), options.max_dist)?;
     println!("Answer to part 2: {}", answer);
 
+    if options.ascii || options.ppm.is_some() {
+        let proximity_region =
+            build_proximity_region(&markers, options.metric.as_ref(), options.max_dist)?;
+        let map = RegionMap::new(&markers, &closest_grid, &proximity_region);
+
+        if options.ascii {
+            print!("{}", map.to_ascii());
+        }
+        if let Some(path) = &options.ppm {
+            fs::write(path, map.to_ppm(PPM_SCALE))?;
+        }
+    }
+
     Ok(())
 }
 
-fn part1(markers: &HashSet<Coord>) -> Result<u32> {
-    let closest_grid = build_closest_grid(markers);
-    let mut count_by_marker: HashMap<&Coord, u32> = markers.iter().zip(iter::repeat(0)).collect();
+/// Command line options.
+struct Options {
+    /// Number of extra cells drawn around the area grid. Answers don't depend on it, as the
+    /// grid is always made large enough to hold every finite area in full.
+    margin: u32,
+    /// How distances between coordinates are measured.
+    metric: Box<dyn Metric>,
+    /// The total distance to all markers below which a cell is considered safe.
+    max_dist: u64,
+    /// Whether to print the region map as coloured text.
+    ascii: bool,
+    /// Where to write the region map as a PPM image, if anywhere.
+    ppm: Option<String>,
+}
 
-    for coord in closest_grid.grid_iter() {
-        if let Some(marker) = closest_grid.get(coord) {
-            if coord.x == 0 || coord.y == 0 || coord.x == closest_grid.width - 1 || coord.y == closest_grid.height - 1 {
-                count_by_marker.remove(marker);
-            } else {
-                count_by_marker.entry(marker).and_modify(|c| *c += 1);
+impl Options {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Options> {
+        let mut options = Options {
+            margin: 0,
+            metric: Box::new(Manhattan),
+            max_dist: DEFAULT_MAX_TOTAL_DIST,
+            ascii: false,
+            ppm: None,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("Missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--margin" => options.margin = value()?.parse()?,
+                "--metric" => options.metric = parse_metric(&value()?)?,
+                "--threshold" => options.max_dist = value()?.parse()?,
+                "--ascii" => options.ascii = true,
+                "--ppm" => options.ppm = Some(value()?),
+                _ => bail!(
+                    "Usage: day06 [--margin CELLS] [--metric manhattan|chebyshev|euclidean|weighted:X,Y] [--threshold DIST] [--ascii] [--ppm FILE] (got {})",
+                    arg
+                ),
             }
         }
+
+        Ok(options)
     }
+}
 
-    Ok(*count_by_marker.values().max().unwrap_or(&0))
+/// Returns the size of the largest finite area in a grid built by [`build_area_grid`].
+fn part1(closest_grid: &Grid<Closest>, infinite_markers: &HashSet<Coord>) -> u32 {
+    let mut count_by_marker: HashMap<Coord, u32> = HashMap::new();
+    for (_, closest) in closest_grid.iter() {
+        if let Closest::Marker(marker) = closest {
+            *count_by_marker.entry(*marker).or_default() += 1;
+        }
+    }
+
+    count_by_marker
+        .iter()
+        .filter(|(marker, _)| !infinite_markers.contains(marker))
+        .map(|(_, count)| *count)
+        .max()
+        .unwrap_or(0)
 }
 
-fn part2(markers: &HashSet<Coord>, max_dist: u32) -> Result<u32> {
-    let proximity_grid = build_proximity_grid(markers, max_dist);
-    Ok(proximity_grid.coords.values().filter(|v| **v).count() as u32)
+fn part2(markers: &HashSet<Coord>, metric: &dyn Metric, max_dist: u64) -> Result<u64> {
+    let proximity_region = build_proximity_region(markers, metric, max_dist)?;
+    Ok(proximity_region.size())
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
 struct Coord {
-    x: u32,
-    y: u32,
+    x: i32,
+    y: i32,
 }
 
 impl Coord {
-    fn dist(&self, other: &Coord) -> u32 {
-        self.x.abs_diff(other.x) + self.y.abs_diff(other.y)
-    }
-}
-
-impl Default for Coord {
-    fn default() -> Self {
-        Self { x: 0, y: 0 }
+    /// Returns the coordinate `(dx, dy)` away from this one, if it is representable.
+    fn offset(&self, dx: i32, dy: i32) -> Option<Coord> {
+        Some(Coord {
+            x: self.x.checked_add(dx)?,
+            y: self.y.checked_add(dy)?,
+        })
     }
 }
 
@@ -71,7 +140,7 @@ impl FromStr for Coord {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         lazy_static! {
-            static ref RE: Regex = Regex::new(r"^(?P<x>\d+),\s*(?P<y>\d+)$").unwrap();
+            static ref RE: Regex = Regex::new(r"^(?P<x>-?\d+),\s*(?P<y>-?\d+)$").unwrap();
         }
 
         let caps = RE
@@ -85,52 +154,140 @@ impl FromStr for Coord {
     }
 }
 
+/// An inclusive rectangular region of coordinates.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Bounds {
+    min: Coord,
+    max: Coord,
+}
+
+impl Bounds {
+    /// Returns the smallest bounds that enclose all of `coords`, or `None` if there are none.
+    fn enclosing<'a>(coords: impl IntoIterator<Item = &'a Coord>) -> Option<Bounds> {
+        coords.into_iter().fold(None, |bounds, coord| {
+            Some(match bounds {
+                None => Bounds {
+                    min: *coord,
+                    max: *coord,
+                },
+                Some(Bounds { min, max }) => Bounds {
+                    min: Coord {
+                        x: cmp::min(min.x, coord.x),
+                        y: cmp::min(min.y, coord.y),
+                    },
+                    max: Coord {
+                        x: cmp::max(max.x, coord.x),
+                        y: cmp::max(max.y, coord.y),
+                    },
+                },
+            })
+        })
+    }
+
+    /// Returns these bounds grown by `margin` cells in every direction.
+    fn expand(&self, margin: u32) -> Bounds {
+        Bounds {
+            min: Coord {
+                x: self.min.x.saturating_sub_unsigned(margin),
+                y: self.min.y.saturating_sub_unsigned(margin),
+            },
+            max: Coord {
+                x: self.max.x.saturating_add_unsigned(margin),
+                y: self.max.y.saturating_add_unsigned(margin),
+            },
+        }
+    }
+
+    fn width(&self) -> usize {
+        self.max.x.abs_diff(self.min.x) as usize + 1
+    }
+
+    fn height(&self) -> usize {
+        self.max.y.abs_diff(self.min.y) as usize + 1
+    }
+
+    fn contains(&self, coord: Coord) -> bool {
+        (self.min.x..=self.max.x).contains(&coord.x) && (self.min.y..=self.max.y).contains(&coord.y)
+    }
+
+    fn on_border(&self, coord: Coord) -> bool {
+        self.contains(coord)
+            && (coord.x == self.min.x
+                || coord.x == self.max.x
+                || coord.y == self.min.y
+                || coord.y == self.max.y)
+    }
+}
+
+/// A dense grid holding one value per coordinate within its bounds, stored row by row.
 struct Grid<T> {
-    width: u32,
-    height: u32,
-    coords: HashMap<Coord, T>,
+    bounds: Bounds,
+    cells: Vec<T>,
+}
+
+impl<T: Clone> Grid<T> {
+    fn new(bounds: Bounds, value: T) -> Grid<T> {
+        Grid {
+            bounds,
+            cells: vec![value; bounds.width() * bounds.height()],
+        }
+    }
 }
 
 impl<T> Grid<T> {
-    fn build<F>(width: u32, height: u32, f: F) -> Grid<T>
+    fn build<F>(bounds: Bounds, f: F) -> Grid<T>
     where
-        F: Fn(Coord) -> Option<T>,
+        F: Fn(Coord) -> T,
     {
-        let mut coords = HashMap::new();
-        for coord in GridIter::new(width, height) {
-            if let Some(t) = f(coord) {
-                coords.insert(coord, t);
-            }
+        Grid {
+            bounds,
+            cells: GridIter::new(bounds).map(f).collect(),
         }
+    }
 
+    fn map<U, F>(self, f: F) -> Grid<U>
+    where
+        F: Fn(T) -> U,
+    {
         Grid {
-            width,
-            height,
-            coords,
+            bounds: self.bounds,
+            cells: self.cells.into_iter().map(f).collect(),
         }
     }
 
+    fn index(&self, coord: Coord) -> Option<usize> {
+        if !self.bounds.contains(coord) {
+            return None;
+        }
+
+        let col = coord.x.abs_diff(self.bounds.min.x) as usize;
+        let row = coord.y.abs_diff(self.bounds.min.y) as usize;
+        Some(row * self.bounds.width() + col)
+    }
+
     fn get(&self, coord: Coord) -> Option<&T> {
-        self.coords.get(&coord)
+        self.index(coord).map(|i| &self.cells[i])
     }
 
-    fn grid_iter(&self) -> GridIter {
-        GridIter::new(self.width, self.height)
+    fn get_mut(&mut self, coord: Coord) -> Option<&mut T> {
+        self.index(coord).map(move |i| &mut self.cells[i])
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (Coord, &T)> {
+        GridIter::new(self.bounds).zip(self.cells.iter())
     }
 }
 
 struct GridIter {
-    width: u32,
-    height: u32,
-    cur: Coord,
+    bounds: Bounds,
+    cur: Option<Coord>,
 }
 
 impl GridIter {
-    fn new(width: u32, height: u32) -> GridIter {
+    fn new(bounds: Bounds) -> GridIter {
         GridIter {
-            width,
-            height,
-            cur: Default::default(),
+            bounds,
+            cur: Some(bounds.min),
         }
     }
 }
@@ -139,85 +296,1078 @@ impl Iterator for GridIter {
     type Item = Coord;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.cur.y >= (self.height) {
-            return None;
-        }
+        let next = self.cur?;
 
-        let next = self.cur;
-
-        self.cur.x += 1;
-        if self.cur.x >= (self.width) {
-            self.cur.x = 0;
-            self.cur.y += 1;
-        }
+        self.cur = if next.x < self.bounds.max.x {
+            Some(Coord {
+                x: next.x + 1,
+                y: next.y,
+            })
+        } else if next.y < self.bounds.max.y {
+            Some(Coord {
+                x: self.bounds.min.x,
+                y: next.y + 1,
+            })
+        } else {
+            None
+        };
 
         Some(next)
     }
 }
 
+/// The marker(s) closest to a cell.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Closest {
+    Marker(Coord),
+    Tie,
+}
+
 fn read_markers(input: &str) -> Result<HashSet<Coord>> {
     input.lines().map(|line| line.parse()).collect()
 }
 
-fn grid_size(coords: &HashSet<Coord>) -> (u32, u32) {
-    let mut x_max = 0;
-    let mut y_max = 0;
-    for coord in coords {
-        x_max = cmp::max(coord.x, x_max);
-        y_max = cmp::max(coord.y, y_max);
+fn marker_bounds(markers: &HashSet<Coord>) -> Result<Bounds> {
+    Bounds::enclosing(markers).ok_or_else(|| anyhow!("No markers specified"))
+}
+
+/// Labels every cell within `bounds`, which must enclose the markers, with its closest
+/// marker according to `metric`.
+fn build_closest_grid(
+    markers: &HashSet<Coord>,
+    metric: &dyn Metric,
+    bounds: Bounds,
+) -> Grid<Closest> {
+    match metric.steps() {
+        Some(steps) => flood_closest_grid(markers, bounds, &steps),
+        None => Grid::build(bounds, |coord| closest_marker(markers, metric, coord)),
+    }
+}
+
+/// Labels every cell within `bounds` with its closest marker by searching outwards from
+/// all markers at once.
+///
+/// This is Dijkstra's algorithm over the grid with one source per marker. The markers
+/// closest to a cell are exactly those closest to the neighbouring cells that lie on a
+/// cheapest path to it, so a cell reached equally cheaply from two different markers
+/// (or from a tied cell) is a tie. Cheapest paths between cells in a rectangle never
+/// need to leave it, so searching only within `bounds` is exact.
+fn flood_closest_grid(markers: &HashSet<Coord>, bounds: Bounds, steps: &[Step]) -> Grid<Closest> {
+    let mut labels: Grid<Option<(u64, Closest)>> = Grid::new(bounds, None);
+    let mut frontier = BinaryHeap::new();
+
+    for marker in markers {
+        *labels.get_mut(*marker).unwrap() = Some((0, Closest::Marker(*marker)));
+        frontier.push(Reverse((0, *marker)));
     }
 
-    (x_max + 1, y_max + 1)
+    while let Some(Reverse((dist, coord))) = frontier.pop() {
+        let (best, closest) = labels.get(coord).unwrap().unwrap();
+        if dist > best {
+            continue;
+        }
+
+        for step in steps {
+            let neighbour = match coord.offset(step.dx, step.dy) {
+                Some(neighbour) => neighbour,
+                None => continue,
+            };
+
+            let dist = dist + step.cost;
+            match labels.get_mut(neighbour) {
+                Some(label @ None) => {
+                    *label = Some((dist, closest));
+                    frontier.push(Reverse((dist, neighbour)));
+                }
+                Some(Some((d, c))) if dist < *d => {
+                    *d = dist;
+                    *c = closest;
+                    frontier.push(Reverse((dist, neighbour)));
+                }
+                Some(Some((d, c))) if dist == *d && *c != closest => *c = Closest::Tie,
+                _ => {}
+            }
+        }
+    }
+
+    // Every cell is reachable from any marker so all have been labelled.
+    labels.map(|label| label.unwrap().1)
+}
+
+/// Finds the closest marker to `coord` by measuring the distance to every marker.
+fn closest_marker(markers: &HashSet<Coord>, metric: &dyn Metric, coord: Coord) -> Closest {
+    let mut closest = Closest::Tie;
+    let mut closest_dist = u64::MAX;
+
+    for marker in markers {
+        let dist = metric.dist(coord, *marker);
+        if dist < closest_dist {
+            closest = Closest::Marker(*marker);
+            closest_dist = dist;
+        } else if dist == closest_dist {
+            closest = Closest::Tie;
+        }
+    }
+
+    closest
 }
 
-fn build_marker_grid(markers: &HashSet<Coord>) -> Grid<bool> {
-    let (width, height) = grid_size(&markers);
-    Grid::build(width, height, |coord| {
-        if markers.contains(&coord) {
-            Some(true)
+/// Builds a closest-marker grid large enough to hold every finite area in full, with
+/// `margin` cells to spare around it. Also returns the markers whose areas are infinite.
+fn build_area_grid(
+    markers: &HashSet<Coord>,
+    metric: &dyn Metric,
+    margin: u32,
+) -> Result<(Grid<Closest>, HashSet<Coord>)> {
+    let bounds = metric
+        .finite_bounds(markers, marker_bounds(markers)?)
+        .expand(margin);
+    let closest_grid = build_closest_grid(markers, metric, bounds);
+    let infinite_markers = metric.infinite_markers(markers, &closest_grid);
+    Ok((closest_grid, infinite_markers))
+}
+
+/// Returns the markers that are closest to at least one cell on the border of the grid.
+fn border_markers(closest_grid: &Grid<Closest>) -> HashSet<Coord> {
+    closest_grid
+        .iter()
+        .filter(|(coord, _)| closest_grid.bounds.on_border(*coord))
+        .filter_map(|(_, closest)| match closest {
+            Closest::Marker(marker) => Some(*marker),
+            Closest::Tie => None,
+        })
+        .collect()
+}
+
+/// Finds every cell whose total distance to all markers is less than `max_dist`, however
+/// far from the markers that region extends.
+///
+/// The total distance is a convex function of the cell, so the region is convex and each
+/// column of it is a single run of cells. When the metric is a sum of per-axis distances
+/// the total splits into `fx(x) + fy(y)`, and each column's run is found by binary search
+/// over the precomputed values of `fy`. Otherwise every candidate cell is measured. Every
+/// distance is at least the difference along either axis, so the total can be no less
+/// than the sum of those differences along either axis, and the search is bounded to the
+/// columns and rows where each of those sums is below `max_dist`.
+fn build_proximity_region(
+    markers: &HashSet<Coord>,
+    metric: &dyn Metric,
+    max_dist: u64,
+) -> Result<ProximityRegion> {
+    let bounds = marker_bounds(markers)?;
+    let xs: Vec<i32> = markers.iter().map(|m| m.x).collect();
+    let ys: Vec<i32> = markers.iter().map(|m| m.y).collect();
+    let axis_total = |axis: Axis, coords: &[i32], metric: &dyn Metric, v: i32| -> u64 {
+        coords.iter().fold(0, |acc, c| {
+            acc.saturating_add(metric.axis_dist(axis, v.abs_diff(*c)).unwrap())
+        })
+    };
+
+    let (fxs, fys) = if metric.axis_dist(Axis::X, 0).is_some() {
+        let fys = sublevel_range(
+            |y| axis_total(Axis::Y, &ys, metric, y),
+            bounds.min.y,
+            bounds.max.y,
+            max_dist,
+        );
+        let fy_min = fys.iter().map(|(_, fy)| *fy).min().unwrap_or(max_dist);
+        let fxs = sublevel_range(
+            |x| axis_total(Axis::X, &xs, metric, x),
+            bounds.min.x,
+            bounds.max.x,
+            max_dist - fy_min,
+        );
+        (fxs, fys)
+    } else {
+        let fxs = sublevel_range(
+            |x| axis_total(Axis::X, &xs, &Manhattan, x),
+            bounds.min.x,
+            bounds.max.x,
+            max_dist,
+        );
+        let fys = sublevel_range(
+            |y| axis_total(Axis::Y, &ys, &Manhattan, y),
+            bounds.min.y,
+            bounds.max.y,
+            max_dist,
+        );
+
+        let total = |coord: Coord| {
+            markers.iter().fold(0u64, |acc, marker| {
+                acc.saturating_add(metric.dist(coord, *marker))
+            })
+        };
+        let spans = fxs
+            .iter()
+            .filter_map(|(x, _)| {
+                let mut inside = fys
+                    .iter()
+                    .map(|(y, _)| Coord { x: *x, y: *y })
+                    .filter(|coord| total(*coord) < max_dist);
+                let first = inside.next()?;
+                let last = inside.next_back().unwrap_or(first);
+                Some(Span {
+                    x: *x,
+                    y_min: first.y,
+                    y_max: last.y,
+                })
+            })
+            .collect();
+
+        return Ok(ProximityRegion { spans });
+    };
+
+    // fy is convex so it falls to its minimum and then rises again, and the cells of each
+    // column within the region are those around the minimum where fy is small enough.
+    let fy_argmin = fys
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, (_, fy))| *fy)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let spans = fxs
+        .iter()
+        .map(|(x, fx)| {
+            let limit = max_dist - fx;
+            let first = fys[..=fy_argmin].partition_point(|(_, fy)| *fy >= limit);
+            let last = fy_argmin + fys[fy_argmin..].partition_point(|(_, fy)| *fy < limit) - 1;
+            Span {
+                x: *x,
+                y_min: fys[first].0,
+                y_max: fys[last].0,
+            }
+        })
+        .collect();
+
+    Ok(ProximityRegion { spans })
+}
+
+/// Returns every `v` for which `f(v) < limit`, paired with `f(v)` and in increasing order.
+///
+/// `f` must be convex and reach its minimum somewhere within `lo..=hi`, so that the values
+/// sought form a single run around that minimum.
+fn sublevel_range<F>(f: F, lo: i32, hi: i32, limit: u64) -> Vec<(i32, u64)>
+where
+    F: Fn(i32) -> u64,
+{
+    let (mut lo, mut hi) = (lo, hi);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if f(mid) <= f(mid + 1) {
+            hi = mid;
         } else {
-            None
+            lo = mid + 1;
         }
+    }
+
+    let mut below = vec![];
+    let mut v = Some(lo);
+    while let Some(fv) = v.map(&f).filter(|fv| *fv < limit) {
+        below.push((v.unwrap(), fv));
+        v = v.unwrap().checked_sub(1);
+    }
+    below.reverse();
+
+    let mut v = lo.checked_add(1);
+    while let Some(fv) = v.map(&f).filter(|fv| *fv < limit) {
+        below.push((v.unwrap(), fv));
+        v = v.unwrap().checked_add(1);
+    }
+
+    below
+}
+
+/// A run of cells in a single column, from `y_min` to `y_max` inclusive.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Span {
+    x: i32,
+    y_min: i32,
+    y_max: i32,
+}
+
+/// A convex region of cells, described by one run of cells per column.
+#[derive(Debug)]
+struct ProximityRegion {
+    /// The region's columns, ordered by `x`.
+    spans: Vec<Span>,
+}
+
+impl ProximityRegion {
+    fn size(&self) -> u64 {
+        self.spans
+            .iter()
+            .map(|span| u64::from(span.y_max.abs_diff(span.y_min)) + 1)
+            .sum()
+    }
+
+    fn contains(&self, coord: Coord) -> bool {
+        match self.spans.binary_search_by_key(&coord.x, |span| span.x) {
+            Ok(i) => (self.spans[i].y_min..=self.spans[i].y_max).contains(&coord.y),
+            Err(_) => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Axis {
+    X,
+    Y,
+}
+
+/// A move between neighbouring cells and what it costs under some metric.
+#[derive(Clone, Copy, Debug)]
+struct Step {
+    dx: i32,
+    dy: i32,
+    cost: u64,
+}
+
+/// A way of measuring the distance between two coordinates.
+///
+/// Distances must never be less than the difference between the coordinates along either
+/// axis.
+trait Metric {
+    fn dist(&self, a: Coord, b: Coord) -> u64;
+
+    /// Returns the distance contributed by a difference of `delta` along `axis`, if this
+    /// metric is the sum of independent distances along each axis.
+    fn axis_dist(&self, _axis: Axis, _delta: u32) -> Option<u64> {
+        None
+    }
+
+    /// Returns the moves between neighbouring cells for which the cheapest path between
+    /// any two cells costs exactly their distance, if this metric can be expressed so.
+    fn steps(&self) -> Option<Vec<Step>> {
+        None
+    }
+
+    /// Returns bounds enclosing the markers and every cell closest to a marker whose area
+    /// is finite, given the bounds of the markers alone.
+    ///
+    /// Beyond the markers' bounds along either axis, each step outwards adds the same to
+    /// the distance to every marker, so the closest marker never changes and any area
+    /// extending that far is infinite. That makes the markers' bounds enough for metrics
+    /// that sum per-axis distances.
+    fn finite_bounds(&self, _markers: &HashSet<Coord>, marker_bounds: Bounds) -> Bounds {
+        marker_bounds
+    }
+
+    /// Returns the markers that are closest to an unbounded number of cells.
+    fn infinite_markers(
+        &self,
+        markers: &HashSet<Coord>,
+        closest_grid: &Grid<Closest>,
+    ) -> HashSet<Coord>;
+}
+
+fn parse_metric(s: &str) -> Result<Box<dyn Metric>> {
+    Ok(match s {
+        "manhattan" => Box::new(Manhattan),
+        "chebyshev" => Box::new(Chebyshev),
+        "euclidean" => Box::new(EuclideanSquared),
+        _ => match s.strip_prefix("weighted:").and_then(|w| w.split_once(',')) {
+            Some((x_weight, y_weight)) => Box::new(WeightedManhattan::new(
+                x_weight.parse()?,
+                y_weight.parse()?,
+            )?),
+            None => bail!("Invalid metric: {}", s),
+        },
     })
 }
 
-fn build_closest_grid(markers: &HashSet<Coord>) -> Grid<Coord> {
-    let marker_grid = build_marker_grid(markers);
-    Grid::build(marker_grid.width, marker_grid.height, |coord| {
-        let mut exclusive = true;
-        let mut closest_marker = None;
-        let mut closest_dist = 0;
-
-        for marker in markers {
-            let dist = coord.dist(marker);
-            if closest_marker.is_some() {
-                if dist < closest_dist {
-                    closest_marker = Some(*marker);
-                    closest_dist = dist;
-                    exclusive = true;
-                } else if dist == closest_dist {
-                    exclusive = false;
+/// Distance travelled when moving only horizontally and vertically.
+struct Manhattan;
+
+impl Metric for Manhattan {
+    fn dist(&self, a: Coord, b: Coord) -> u64 {
+        u64::from(a.x.abs_diff(b.x)) + u64::from(a.y.abs_diff(b.y))
+    }
+
+    fn axis_dist(&self, _: Axis, delta: u32) -> Option<u64> {
+        Some(u64::from(delta))
+    }
+
+    fn steps(&self) -> Option<Vec<Step>> {
+        WeightedManhattan::new(1, 1).ok()?.steps()
+    }
+
+    fn infinite_markers(&self, _: &HashSet<Coord>, closest_grid: &Grid<Closest>) -> HashSet<Coord> {
+        // Stepping outwards from a border cell increases the distance to every marker by
+        // the same amount, so a marker closest to a border cell is closest to all cells
+        // beyond it too. Conversely, any unbounded area must cross the border.
+        border_markers(closest_grid)
+    }
+}
+
+/// Manhattan distance where horizontal and vertical moves have different costs.
+struct WeightedManhattan {
+    x_weight: u64,
+    y_weight: u64,
+}
+
+impl WeightedManhattan {
+    fn new(x_weight: u64, y_weight: u64) -> Result<WeightedManhattan> {
+        if x_weight == 0 || y_weight == 0 {
+            bail!("Metric weights must be positive: {},{}", x_weight, y_weight);
+        }
+
+        Ok(WeightedManhattan { x_weight, y_weight })
+    }
+}
+
+impl Metric for WeightedManhattan {
+    fn dist(&self, a: Coord, b: Coord) -> u64 {
+        self.x_weight * u64::from(a.x.abs_diff(b.x)) + self.y_weight * u64::from(a.y.abs_diff(b.y))
+    }
+
+    fn axis_dist(&self, axis: Axis, delta: u32) -> Option<u64> {
+        let weight = match axis {
+            Axis::X => self.x_weight,
+            Axis::Y => self.y_weight,
+        };
+        Some(weight.saturating_mul(u64::from(delta)))
+    }
+
+    fn steps(&self) -> Option<Vec<Step>> {
+        Some(vec![
+            Step {
+                dx: -1,
+                dy: 0,
+                cost: self.x_weight,
+            },
+            Step {
+                dx: 1,
+                dy: 0,
+                cost: self.x_weight,
+            },
+            Step {
+                dx: 0,
+                dy: -1,
+                cost: self.y_weight,
+            },
+            Step {
+                dx: 0,
+                dy: 1,
+                cost: self.y_weight,
+            },
+        ])
+    }
+
+    fn infinite_markers(&self, _: &HashSet<Coord>, closest_grid: &Grid<Closest>) -> HashSet<Coord> {
+        // As for unweighted Manhattan distance, each step outwards from the border costs
+        // the same for every marker.
+        border_markers(closest_grid)
+    }
+}
+
+/// Distance travelled when diagonal moves cost the same as horizontal and vertical ones.
+struct Chebyshev;
+
+impl Metric for Chebyshev {
+    fn dist(&self, a: Coord, b: Coord) -> u64 {
+        u64::from(cmp::max(a.x.abs_diff(b.x), a.y.abs_diff(b.y)))
+    }
+
+    fn steps(&self) -> Option<Vec<Step>> {
+        let mut steps = vec![];
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                if (dx, dy) != (0, 0) {
+                    steps.push(Step { dx, dy, cost: 1 });
                 }
-            } else {
-                closest_marker = Some(*marker);
-                closest_dist = dist;
             }
         }
 
-        if exclusive {
-            closest_marker
-        } else {
-            None
+        Some(steps)
+    }
+
+    fn finite_bounds(&self, markers: &HashSet<Coord>, _: Bounds) -> Bounds {
+        // Chebyshev distance is half the Manhattan distance along the diagonals u = x + y
+        // and v = x - y, so as for Manhattan distance every finite area lies within the
+        // markers' bounds along those diagonals. Those bounds form a diamond, which is
+        // enclosed by the box returned.
+        let diagonals = |m: &Coord| {
+            (
+                i64::from(m.x) + i64::from(m.y),
+                i64::from(m.x) - i64::from(m.y),
+            )
+        };
+        let (u_min, u_max, v_min, v_max) = markers.iter().map(diagonals).fold(
+            (i64::MAX, i64::MIN, i64::MAX, i64::MIN),
+            |(u_min, u_max, v_min, v_max), (u, v)| {
+                (u_min.min(u), u_max.max(u), v_min.min(v), v_max.max(v))
+            },
+        );
+
+        let coord = |x: i64, y: i64| Coord {
+            x: x.clamp(i32::MIN.into(), i32::MAX.into()) as i32,
+            y: y.clamp(i32::MIN.into(), i32::MAX.into()) as i32,
+        };
+        Bounds {
+            min: coord((u_min + v_min).div_euclid(2), (u_min - v_max).div_euclid(2)),
+            max: coord(
+                (u_max + v_max + 1).div_euclid(2),
+                (u_max - v_min + 1).div_euclid(2),
+            ),
         }
-    })
+    }
+
+    fn infinite_markers(&self, markers: &HashSet<Coord>, _: &Grid<Closest>) -> HashSet<Coord> {
+        // Far from the markers, the closest marker in any direction is the one furthest
+        // along it. Travelling diagonally away from a marker it stays strictly closest
+        // unless another marker is at least as far along both axes of that diagonal; in
+        // every other direction only a single marker that is furthest along the dominant
+        // axis can be strictly closest, and such a marker is also undominated diagonally.
+        const QUADRANTS: [(i64, i64); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+        markers
+            .iter()
+            .filter(|marker| {
+                QUADRANTS.iter().any(|(sx, sy)| {
+                    !markers.iter().any(|other| {
+                        other != *marker
+                            && sx * i64::from(other.x) >= sx * i64::from(marker.x)
+                            && sy * i64::from(other.y) >= sy * i64::from(marker.y)
+                    })
+                })
+            })
+            .copied()
+            .collect()
+    }
+}
+
+/// Squared straight-line distance, which orders cells the same as Euclidean distance while
+/// staying in integers.
+struct EuclideanSquared;
+
+impl Metric for EuclideanSquared {
+    fn dist(&self, a: Coord, b: Coord) -> u64 {
+        let dx = u64::from(a.x.abs_diff(b.x));
+        let dy = u64::from(a.y.abs_diff(b.y));
+        dx * dx + dy * dy
+    }
+
+    fn axis_dist(&self, _: Axis, delta: u32) -> Option<u64> {
+        Some(u64::from(delta) * u64::from(delta))
+    }
+
+    fn finite_bounds(&self, markers: &HashSet<Coord>, marker_bounds: Bounds) -> Bounds {
+        // A finite Voronoi cell is the convex polygon with the cell's Voronoi vertices as
+        // its corners, so the box around those vertices encloses it. This can reach far
+        // beyond the markers when a cell is a thin sliver between nearly collinear ones.
+        let infinite_markers = convex_hull_boundary(markers);
+        let (mut min, mut max) = (
+            (
+                f64::from(marker_bounds.min.x),
+                f64::from(marker_bounds.min.y),
+            ),
+            (
+                f64::from(marker_bounds.max.x),
+                f64::from(marker_bounds.max.y),
+            ),
+        );
+        for (x, y) in voronoi_vertices(markers, |triple| {
+            triple.iter().any(|m| !infinite_markers.contains(m))
+        }) {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+
+        // Conversions from floats saturate at the limits of the integer type.
+        Bounds {
+            min: Coord {
+                x: min.0.floor() as i32,
+                y: min.1.floor() as i32,
+            },
+            max: Coord {
+                x: max.0.ceil() as i32,
+                y: max.1.ceil() as i32,
+            },
+        }
+    }
+
+    fn infinite_markers(&self, markers: &HashSet<Coord>, _: &Grid<Closest>) -> HashSet<Coord> {
+        // A marker's Voronoi cell is unbounded exactly when the marker lies on the boundary
+        // of the markers' convex hull.
+        convex_hull_boundary(markers)
+    }
 }
 
-fn build_proximity_grid(markers: &HashSet<Coord>, max_dist: u32) -> Grid<bool> {
-    let marker_grid = build_marker_grid(markers);
-    Grid::build(marker_grid.width, marker_grid.height, |coord| {
-        let dist = markers
+/// Returns the Voronoi vertices of the markers (under Euclidean distance) that are corners
+/// of the cell of at least one marker in a triple accepted by `wanted`.
+///
+/// Each vertex is the centre of a circle through three markers with no marker inside it.
+/// Centres are computed in floating point, so a marker only counts as inside if it is
+/// clearly so; erring this way can only add vertices, never miss any.
+fn voronoi_vertices<F>(markers: &HashSet<Coord>, wanted: F) -> Vec<(f64, f64)>
+where
+    F: Fn(&[Coord; 3]) -> bool,
+{
+    const TOLERANCE: f64 = 1e-9;
+
+    let markers: Vec<Coord> = markers.iter().copied().collect();
+    let point = |m: Coord| (f64::from(m.x), f64::from(m.y));
+    let dist_sq =
+        |(x1, y1): (f64, f64), (x2, y2): (f64, f64)| (x1 - x2).powi(2) + (y1 - y2).powi(2);
+
+    let mut vertices = vec![];
+    for i in 0..markers.len() {
+        for j in i + 1..markers.len() {
+            for k in j + 1..markers.len() {
+                let triple = [markers[i], markers[j], markers[k]];
+                if !wanted(&triple) {
+                    continue;
+                }
+
+                // Work relative to the first marker to keep the numbers small.
+                let (ax, ay) = point(triple[0]);
+                let (bx, by) = (point(triple[1]).0 - ax, point(triple[1]).1 - ay);
+                let (cx, cy) = (point(triple[2]).0 - ax, point(triple[2]).1 - ay);
+                let d = 2.0 * (bx * cy - by * cx);
+                if d == 0.0 {
+                    continue;
+                }
+                let (b_sq, c_sq) = (bx * bx + by * by, cx * cx + cy * cy);
+                let centre = (
+                    ax + (cy * b_sq - by * c_sq) / d,
+                    ay + (bx * c_sq - cx * b_sq) / d,
+                );
+
+                let radius_sq = dist_sq(centre, (ax, ay));
+                let inside = markers
+                    .iter()
+                    .any(|m| dist_sq(centre, point(*m)) < radius_sq * (1.0 - TOLERANCE));
+                if !inside {
+                    vertices.push(centre);
+                }
+            }
+        }
+    }
+
+    vertices
+}
+
+/// Returns the markers that lie on the boundary of the convex hull of all markers.
+fn convex_hull_boundary(markers: &HashSet<Coord>) -> HashSet<Coord> {
+    fn cross(o: Coord, a: Coord, b: Coord) -> i64 {
+        let (ox, oy) = (i64::from(o.x), i64::from(o.y));
+        (i64::from(a.x) - ox) * (i64::from(b.y) - oy)
+            - (i64::from(a.y) - oy) * (i64::from(b.x) - ox)
+    }
+
+    // Andrew's monotone chain, keeping only the hull's corners.
+    let mut sorted: Vec<Coord> = markers.iter().copied().collect();
+    sorted.sort();
+
+    let mut hull: Vec<Coord> = vec![];
+    for pass in [sorted.clone(), sorted.into_iter().rev().collect()] {
+        let start = hull.len();
+        for coord in pass {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], coord) <= 0
+            {
+                hull.pop();
+            }
+            hull.push(coord);
+        }
+        hull.pop();
+    }
+
+    // With fewer than three corners the markers are collinear and every area is unbounded.
+    if hull.len() < 3 {
+        return markers.clone();
+    }
+
+    let edges: Vec<(Coord, Coord)> = hull
+        .iter()
+        .copied()
+        .zip(hull.iter().copied().cycle().skip(1))
+        .collect();
+    markers
+        .iter()
+        .filter(|marker| {
+            edges.iter().any(|(a, b)| {
+                cross(*a, *b, **marker) == 0
+                    && (cmp::min(a.x, b.x)..=cmp::max(a.x, b.x)).contains(&marker.x)
+                    && (cmp::min(a.y, b.y)..=cmp::max(a.y, b.y)).contains(&marker.y)
+            })
+        })
+        .copied()
+        .collect()
+}
+
+/// The characters used to label markers in text output, reused in turn if there are more
+/// markers than characters.
+const MARKER_LABELS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Rgb(u8, u8, u8);
+
+impl Rgb {
+    const MARKER: Rgb = Rgb(0, 0, 0);
+    const TIE: Rgb = Rgb(128, 128, 128);
+    const SAFE: Rgb = Rgb(255, 255, 255);
+
+    /// Returns the `i`th of a sequence of colours whose hues are spread around the colour
+    /// wheel so that neighbouring indices are easy to tell apart.
+    fn distinct(i: usize) -> Rgb {
+        const GOLDEN_RATIO_CONJUGATE: f64 = 0.618_033_988_749_895;
+        let hue = (i as f64 * GOLDEN_RATIO_CONJUGATE).fract() * 6.0;
+        let (saturation, value) = (0.65, 0.9);
+
+        let chroma = value * saturation;
+        let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+        let (r, g, b) = match hue as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+
+        let m = value - chroma;
+        let channel = |c: f64| ((c + m) * 255.0).round() as u8;
+        Rgb(channel(r), channel(g), channel(b))
+    }
+
+    /// Mixes `amount` (between 0 and 1) of `other` into this colour.
+    fn blend(self, other: Rgb, amount: f64) -> Rgb {
+        let mix =
+            |a: u8, b: u8| (f64::from(a) * (1.0 - amount) + f64::from(b) * amount).round() as u8;
+        Rgb(
+            mix(self.0, other.0),
+            mix(self.1, other.1),
+            mix(self.2, other.2),
+        )
+    }
+}
+
+/// How a single cell of a region map is drawn.
+struct CellStyle {
+    label: char,
+    colour: Rgb,
+    is_marker: bool,
+    is_safe: bool,
+}
+
+/// A picture of the area closest to each marker, with the safe region overlaid.
+struct RegionMap<'a> {
+    closest_grid: &'a Grid<Closest>,
+    proximity_region: &'a ProximityRegion,
+    /// The label, colour and coordinate of each marker, ordered by label.
+    legend: Vec<(char, Rgb, Coord)>,
+    /// The label and colour of each marker.
+    styles: HashMap<Coord, (char, Rgb)>,
+}
+
+impl<'a> RegionMap<'a> {
+    fn new(
+        markers: &HashSet<Coord>,
+        closest_grid: &'a Grid<Closest>,
+        proximity_region: &'a ProximityRegion,
+    ) -> RegionMap<'a> {
+        // Sort so that each marker keeps the same label and colour between runs.
+        let mut markers: Vec<Coord> = markers.iter().copied().collect();
+        markers.sort_by_key(|coord| (coord.y, coord.x));
+
+        let legend: Vec<(char, Rgb, Coord)> = markers
+            .into_iter()
+            .enumerate()
+            .map(|(i, marker)| {
+                let label = MARKER_LABELS[i % MARKER_LABELS.len()] as char;
+                (label, Rgb::distinct(i), marker)
+            })
+            .collect();
+        let styles = legend
             .iter()
-            .fold(0, |acc, b| acc + b.dist(&coord));
-        Some(dist < max_dist)
-    })
+            .map(|(label, colour, marker)| (*marker, (*label, *colour)))
+            .collect();
+
+        RegionMap {
+            closest_grid,
+            proximity_region,
+            legend,
+            styles,
+        }
+    }
+
+    fn style(&self, coord: Coord, closest: &Closest) -> CellStyle {
+        let is_safe = self.proximity_region.contains(coord);
+        match closest {
+            Closest::Marker(marker) => {
+                let (label, colour) = self.styles[marker];
+                CellStyle {
+                    label,
+                    colour,
+                    is_marker: coord == *marker,
+                    is_safe,
+                }
+            }
+            Closest::Tie => CellStyle {
+                label: '.',
+                colour: Rgb::TIE,
+                is_marker: false,
+                is_safe,
+            },
+        }
+    }
+
+    /// Renders the map as text coloured with ANSI escape codes, followed by a legend.
+    ///
+    /// Markers are shown in bold, inverted, and each cell in a marker's area shows that
+    /// marker's label. Tied cells are grey dots and the safe region has a dark background.
+    fn to_ascii(&self) -> String {
+        const RESET: &str = "\x1b[0m";
+
+        let mut out = String::new();
+        let width = self.closest_grid.bounds.width();
+        for (i, (coord, closest)) in self.closest_grid.iter().enumerate() {
+            let style = self.style(coord, closest);
+            let Rgb(r, g, b) = style.colour;
+            write!(out, "\x1b[38;2;{};{};{}m", r, g, b).unwrap();
+            if style.is_marker {
+                out.push_str("\x1b[1;7m");
+            }
+            if style.is_safe {
+                out.push_str("\x1b[48;2;48;48;48m");
+            }
+            out.push(style.label);
+            out.push_str(RESET);
+
+            if (i + 1) % width == 0 {
+                out.push('\n');
+            }
+        }
+
+        for (label, Rgb(r, g, b), marker) in &self.legend {
+            writeln!(
+                out,
+                "\x1b[38;2;{};{};{}m{}{} ({}, {})",
+                r, g, b, label, RESET, marker.x, marker.y
+            )
+            .unwrap();
+        }
+
+        out
+    }
+
+    /// Renders the map as a binary PPM image with each cell drawn as a `scale` x `scale`
+    /// square. Markers are black, tied cells grey and the safe region is lightened.
+    fn to_ppm(&self, scale: usize) -> Vec<u8> {
+        let width = self.closest_grid.bounds.width();
+        let height = self.closest_grid.bounds.height();
+        let colours: Vec<Rgb> = self
+            .closest_grid
+            .iter()
+            .map(|(coord, closest)| {
+                let style = self.style(coord, closest);
+                match (style.is_marker, style.is_safe) {
+                    (true, _) => Rgb::MARKER,
+                    (false, true) => style.colour.blend(Rgb::SAFE, 0.6),
+                    (false, false) => style.colour,
+                }
+            })
+            .collect();
+
+        let mut out = format!("P6\n{} {}\n255\n", width * scale, height * scale).into_bytes();
+        for row in colours.chunks(width) {
+            for _ in 0..scale {
+                for Rgb(r, g, b) in row {
+                    for _ in 0..scale {
+                        out.extend_from_slice(&[*r, *g, *b]);
+                    }
+                }
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const SAMPLE: &str = "1, 1\n1, 6\n8, 3\n3, 4\n5, 5\n8, 9\n";
+
+    fn random_markers(rng: &mut StdRng, count: usize, range: i32) -> HashSet<Coord> {
+        (0..count)
+            .map(|_| Coord {
+                x: rng.gen_range(-range..=range),
+                y: rng.gen_range(-range..=range),
+            })
+            .collect()
+    }
+
+    fn largest_area(markers: &HashSet<Coord>, metric: &dyn Metric) -> u32 {
+        let (closest_grid, infinite_markers) = build_area_grid(markers, metric, 0).unwrap();
+        part1(&closest_grid, &infinite_markers)
+    }
+
+    /// Finds the largest finite area by measuring the distance from every cell within
+    /// `bounds` to every marker. Markers closest to any cell on the border of the bounds
+    /// are taken to have infinite areas.
+    fn brute_force_part1(markers: &HashSet<Coord>, metric: &dyn Metric, bounds: Bounds) -> u32 {
+        let closest_grid = Grid::build(bounds, |coord| closest_marker(markers, metric, coord));
+        let infinite_markers = border_markers(&closest_grid);
+
+        let mut count_by_marker: HashMap<Coord, u32> = HashMap::new();
+        for (_, closest) in closest_grid.iter() {
+            if let Closest::Marker(marker) = closest {
+                if !infinite_markers.contains(marker) {
+                    *count_by_marker.entry(*marker).or_default() += 1;
+                }
+            }
+        }
+
+        count_by_marker.values().copied().max().unwrap_or(0)
+    }
+
+    #[test]
+    fn sample() {
+        let markers = read_markers(SAMPLE).unwrap();
+        assert_eq!(largest_area(&markers, &Manhattan), 17);
+        assert_eq!(part2(&markers, &Manhattan, 32).unwrap(), 16);
+    }
+
+    /// Counts the cells whose total distance to all markers is less than `max_dist` by
+    /// measuring every cell that could possibly qualify.
+    fn brute_force_part2(markers: &HashSet<Coord>, metric: &dyn Metric, max_dist: u64) -> u64 {
+        // A cell `d` cells beyond the markers along either axis is at least `d` from every
+        // marker.
+        let margin = (max_dist / markers.len() as u64 + 1) as u32;
+        GridIter::new(marker_bounds(markers).unwrap().expand(margin))
+            .filter(|coord| markers.iter().map(|m| metric.dist(*coord, *m)).sum::<u64>() < max_dist)
+            .count() as u64
+    }
+
+    #[test]
+    fn sample_with_other_metrics() {
+        let markers = read_markers(SAMPLE).unwrap();
+        assert_eq!(part2(&markers, &Manhattan, 10000).unwrap(), 5554416);
+        assert_eq!(part2(&markers, &Chebyshev, 10000).unwrap(), 11108864);
+        assert_eq!(part2(&markers, &EuclideanSquared, 10000).unwrap(), 5184);
+    }
+
+    #[test]
+    fn proximity_regions_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(31);
+        let metrics: [&dyn Metric; 4] = [
+            &Manhattan,
+            &Chebyshev,
+            &EuclideanSquared,
+            &WeightedManhattan::new(1, 4).unwrap(),
+        ];
+        for _ in 0..20 {
+            let count = rng.gen_range(1..=8);
+            let markers = random_markers(&mut rng, count, 10);
+            let max_dist = rng.gen_range(0..400);
+            for metric in metrics {
+                assert_eq!(
+                    part2(&markers, metric, max_dist).unwrap(),
+                    brute_force_part2(&markers, metric, max_dist),
+                    "markers {:?} below {}",
+                    markers,
+                    max_dist
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn flood_labels_ties_like_direct_measurement() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let metrics: [&dyn Metric; 3] = [
+            &Manhattan,
+            &Chebyshev,
+            &WeightedManhattan::new(2, 3).unwrap(),
+        ];
+        for _ in 0..20 {
+            let markers = random_markers(&mut rng, 8, 15);
+            let bounds = marker_bounds(&markers).unwrap().expand(3);
+            for metric in metrics {
+                let flooded = flood_closest_grid(&markers, bounds, &metric.steps().unwrap());
+                for (coord, closest) in flooded.iter() {
+                    assert_eq!(
+                        *closest,
+                        closest_marker(&markers, metric, coord),
+                        "{:?} with markers {:?}",
+                        coord,
+                        markers
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn finite_areas_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(29);
+        let metrics: [&dyn Metric; 4] = [
+            &Manhattan,
+            &Chebyshev,
+            &EuclideanSquared,
+            &WeightedManhattan::new(3, 1).unwrap(),
+        ];
+        for _ in 0..20 {
+            let count = rng.gen_range(3..=7);
+            let markers = random_markers(&mut rng, count, 6);
+            for metric in metrics {
+                // If the bounds were too small, any area cut short by them would either
+                // reach the border of these wider bounds, or have more cells within them.
+                let bounds = metric
+                    .finite_bounds(&markers, marker_bounds(&markers).unwrap())
+                    .expand(30);
+                assert_eq!(
+                    largest_area(&markers, metric),
+                    brute_force_part1(&markers, metric, bounds),
+                    "markers {:?}",
+                    markers
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn counts_euclidean_slivers_beyond_the_markers() {
+        // The area closest to (5, 4) is a thin sliver reaching out as far as x = 71.
+        let markers = read_markers("-3,4\n5,4\n-3,0\n1,-6\n6,6\n3,2\n-6,-4\n").unwrap();
+        let bounds = marker_bounds(&markers).unwrap().expand(150);
+        assert_eq!(brute_force_part1(&markers, &EuclideanSquared, bounds), 233);
+        assert_eq!(largest_area(&markers, &EuclideanSquared), 233);
+    }
+
+    #[test]
+    fn signed_coordinates_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(28);
+        for _ in 0..20 {
+            let markers = random_markers(&mut rng, 10, 30);
+            assert_eq!(
+                largest_area(&markers, &Manhattan),
+                brute_force_part1(
+                    &markers,
+                    &Manhattan,
+                    marker_bounds(&markers).unwrap().expand(5)
+                ),
+                "markers {:?}",
+                markers
+            );
+        }
+
+        // Entirely negative coordinates, including the most negative representable.
+        let markers = read_markers("-1, -1\n-1, -6\n-8, -3\n-3, -4\n-5, -5\n-8, -9\n").unwrap();
+        assert_eq!(largest_area(&markers, &Manhattan), 17);
+        let markers: HashSet<Coord> = read_markers(SAMPLE)
+            .unwrap()
+            .iter()
+            .map(|m| Coord {
+                x: i32::MIN + m.x,
+                y: i32::MIN + m.y,
+            })
+            .collect();
+        assert_eq!(largest_area(&markers, &Manhattan), 17);
+        assert_eq!(part2(&markers, &Manhattan, 32).unwrap(), 16);
+    }
 }
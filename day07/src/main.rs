@@ -1,254 +1,396 @@
-use anyhow::{anyhow, Result};
+mod scheduler;
+
+use anyhow::{anyhow, bail, Result};
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::collections::{HashMap, HashSet};
+use scheduler::{Alphabetical, Graph, MostDependents, Scheduler, WorkerSpec};
 use std::{
+    env, fs,
     io::{self, Read},
-    str::FromStr,
+    time::Duration,
 };
 
-const WORKER_COUNT: u32 = 5;
-const WORK_BASE_TIME_SECONDS: u32 = 60;
+const DEFAULT_WORKER_COUNT: usize = 5;
+const DEFAULT_WORK_BASE_TIME_SECONDS: u32 = 60;
+const DEFAULT_SEARCH_BUDGET_SECONDS: u64 = 10;
 
 fn main() -> Result<()> {
+    let options = Options::from_args(env::args().skip(1))?;
+    let scheduler = &options.scheduler;
+
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
 
-    let deps = read_deps(&input)?;
-    let answer = part1(&deps)?;
+    let graph = read_graph(&input)?;
+    let answer = part1(&graph, scheduler)?;
     println!("Answer to part 1: {}", answer);
 
-    let mut worker_pool = WorkerPool::new(WORKER_COUNT);
-    let answer = part2(&deps, &mut worker_pool)?;
+    let answer = part2(&graph, scheduler)?;
     println!("Answer to part 2: {} seconds", answer);
 
+    if let Some(budget) = options.search_budget {
+        let greedy = scheduler.run(&graph)?.makespan;
+        let optimised = scheduler.optimise(&graph, budget, |makespan, elapsed| {
+            eprintln!(
+                "Found a schedule taking {} seconds after {:.1?}",
+                makespan, elapsed
+            );
+        })?;
+
+        let best = optimised.schedule.makespan;
+        let gap = greedy - best;
+        println!(
+            "Best schedule takes {} seconds ({}, {} nodes searched)",
+            best,
+            if optimised.optimal {
+                "optimal"
+            } else {
+                "search budget exhausted"
+            },
+            optimised.nodes
+        );
+        println!(
+            "Greedy schedule is {} seconds ({:.1}%) longer",
+            gap,
+            100.0 * f64::from(gap) / f64::from(best.max(1))
+        );
+    }
+
+    if options.gantt || options.json {
+        let schedule = scheduler.run(&graph)?;
+        if options.gantt {
+            print!("{}", schedule.to_gantt());
+        }
+        if options.json {
+            println!("{}", schedule.to_json()?);
+        }
+    }
+
     Ok(())
 }
 
-fn part1(deps: &DepsByStep) -> Result<String> {
-    let mut completed_steps = HashSet::new();
-    let mut available_steps = deps
-        .iter()
-        .filter_map(|(s, v)| if v.is_empty() { Some(*s) } else { None })
-        .collect::<HashSet<Step>>();
-
-    let mut result = String::from("");
-    while !available_steps.is_empty() {
-        let chosen = *available_steps.iter().max_by(|s1, s2| s2.cmp(s1)).unwrap();
-        available_steps.remove(&chosen);
-        completed_steps.insert(chosen);
-        result += chosen.to_string().as_str();
-
-        let unlocked_steps = deps
-            .iter()
-            .filter_map(|(s, v)| {
-                if !completed_steps.contains(s) && v.is_subset(&completed_steps) {
-                    Some(*s)
-                } else {
-                    None
-                }
-            })
-            .collect::<HashSet<Step>>();
-        available_steps.extend(unlocked_steps);
-    }
+fn part1(graph: &Graph, scheduler: &Scheduler) -> Result<String> {
+    Ok(scheduler.order(graph)?.concat())
+}
 
-    Ok(result)
+fn part2(graph: &Graph, scheduler: &Scheduler) -> Result<u32> {
+    Ok(scheduler.run(graph)?.makespan)
 }
 
-fn part2(deps: &DepsByStep, worker_pool: &mut WorkerPool) -> Result<u32> {
-    let mut completed_steps = HashSet::new();
-    let mut assigned_steps = HashSet::new();
-    let mut available_steps = deps
-        .iter()
-        .filter_map(|(s, v)| if v.is_empty() { Some(*s) } else { None })
-        .collect::<HashSet<Step>>();
-
-    let mut seconds = 0;
-    loop {
-        if completed_steps.len() == deps.len() {
-            break;
-        }
+/// Command line options.
+struct Options {
+    scheduler: Scheduler,
+    /// Whether to print the schedule as a table.
+    gantt: bool,
+    /// Whether to print the schedule as JSON.
+    json: bool,
+    /// How long to spend searching for an optimal schedule, if at all.
+    search_budget: Option<Duration>,
+}
 
-        while !available_steps.is_empty() && worker_pool.is_ready() {
-            let chosen = *available_steps.iter().max_by(|s1, s2| s2.cmp(s1)).unwrap();
-            let duration = WORK_BASE_TIME_SECONDS + (chosen as u8 - b'A') as u32 + 1;
-            worker_pool.assign(chosen, duration);
-            assigned_steps.insert(chosen);
-            available_steps.remove(&chosen);
+impl Options {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Options> {
+        let mut worker_count = DEFAULT_WORKER_COUNT;
+        let mut base_time = DEFAULT_WORK_BASE_TIME_SECONDS;
+        let mut flat_cost = false;
+        let mut most_dependents = false;
+        let mut roster = None;
+        let mut gantt = false;
+        let mut json = false;
+        let mut search_budget = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("Missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--workers" => worker_count = value()?.parse()?,
+                "--base-time" => base_time = value()?.parse()?,
+                "--cost" => match value()?.as_str() {
+                    "letter" => flat_cost = false,
+                    "flat" => flat_cost = true,
+                    cost => bail!("Invalid cost function: {}", cost),
+                },
+                "--priority" => match value()?.as_str() {
+                    "alphabetical" => most_dependents = false,
+                    "most-dependents" => most_dependents = true,
+                    priority => bail!("Invalid priority policy: {}", priority),
+                },
+                "--roster" => roster = Some(read_roster(&fs::read_to_string(value()?)?)?),
+                "--gantt" => gantt = true,
+                "--json" => json = true,
+                "--optimal" => {
+                    search_budget = Some(Duration::from_secs(DEFAULT_SEARCH_BUDGET_SECONDS))
+                }
+                "--budget" => search_budget = Some(Duration::from_secs_f64(value()?.parse()?)),
+                _ => bail!(
+                    "Usage: day07 [--workers N] [--base-time SECONDS] [--cost letter|flat] [--priority alphabetical|most-dependents] [--roster FILE] [--gantt] [--json] [--optimal] [--budget SECONDS] (got {})",
+                    arg
+                ),
+            }
         }
 
-        loop {
-            worker_pool.tick();
-            seconds += 1;
-            let batch_completed = worker_pool.receive();
-            if !batch_completed.is_empty() {
-                completed_steps.extend(&batch_completed);
-                assigned_steps = assigned_steps
-                    .difference(&batch_completed)
-                    .cloned()
-                    .collect();
-
-                let unlocked_steps = deps
-                    .iter()
-                    .filter_map(|(s, v)| {
-                        if v.is_subset(&completed_steps)
-                            && !completed_steps.contains(s)
-                            && !assigned_steps.contains(s)
-                        {
-                            Some(*s)
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<HashSet<Step>>();
-                available_steps.extend(unlocked_steps);
-                break;
-            }
+        let mut scheduler = Scheduler::new(worker_count, base_time);
+        if let Some(roster) = roster {
+            scheduler = scheduler.with_roster(roster);
         }
-    }
+        if flat_cost {
+            scheduler = scheduler.with_cost(|_| Some(0));
+        }
+        scheduler = if most_dependents {
+            scheduler.with_priority(MostDependents)
+        } else {
+            scheduler.with_priority(Alphabetical)
+        };
 
-    Ok(seconds)
+        Ok(Options {
+            scheduler,
+            gantt,
+            json,
+            search_budget,
+        })
+    }
 }
 
-type Step = char;
-
-type DepsByStep = HashMap<Step, HashSet<Step>>;
-
-#[derive(Eq, Hash, PartialEq)]
-struct DepPair(Step, Step);
+/// A single line of the input.
+enum Line {
+    /// The first step must be finished before the second can begin.
+    Dep(String, String),
+    /// The step takes the given number of seconds.
+    Duration(String, u32),
+    /// The step may only be run by workers with all of the given tags.
+    Tags(String, Vec<String>),
+}
 
-impl FromStr for DepPair {
+impl std::str::FromStr for Line {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         lazy_static! {
-            static ref RE: Regex =
-                Regex::new(r"^Step (?P<s1>[A-Z]) must be finished before step (?P<s2>[A-Z])")
+            static ref DEP_RE: Regex =
+                Regex::new(r"^Step (?P<s1>\S+) must be finished before step (?P<s2>\S+) can begin")
                     .unwrap();
+            static ref DURATION_RE: Regex =
+                Regex::new(r"^Step (?P<s>\S+) takes (?P<secs>\d+) seconds?").unwrap();
+            static ref TAGS_RE: Regex =
+                Regex::new(r"^Step (?P<s>\S+) requires (?:tags? )?(?P<tags>[^.]+)").unwrap();
+        }
+
+        if let Some(caps) = DEP_RE.captures(s) {
+            return Ok(Line::Dep(caps["s1"].to_string(), caps["s2"].to_string()));
         }
 
-        let caps = RE
+        if let Some(caps) = TAGS_RE.captures(s) {
+            let tags = caps["tags"]
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|tag| !tag.is_empty())
+                .map(String::from)
+                .collect();
+            return Ok(Line::Tags(caps["s"].to_string(), tags));
+        }
+
+        let caps = DURATION_RE
             .captures(s)
             .ok_or_else(|| anyhow!("Invalid line: {}", s))?;
-
-        Ok(DepPair(caps["s2"].parse()?, caps["s1"].parse()?))
+        Ok(Line::Duration(caps["s"].to_string(), caps["secs"].parse()?))
     }
 }
 
-struct WorkerPool {
-    workers: Vec<Worker>,
-}
-
-impl WorkerPool {
-    fn new(size: u32) -> WorkerPool {
-        let mut worker_pool = WorkerPool { workers: vec![] };
-        for _ in 0..size {
-            worker_pool.workers.push(Worker::new());
+fn read_graph(input: &str) -> Result<Graph> {
+    let mut graph = Graph::new();
+    for line in input.lines().filter(|line| !line.trim().is_empty()) {
+        match line.parse()? {
+            Line::Dep(dep, step) => {
+                graph.add_step(&dep);
+                graph.add_dep(&step, &dep);
+            }
+            Line::Duration(step, duration) => graph.set_duration(&step, duration),
+            Line::Tags(step, tags) => graph.require_tags(&step, tags.iter().map(String::as_str)),
         }
-        worker_pool
     }
 
-    fn assign(&mut self, step: Step, duration: u32) {
-        for worker in &mut self.workers {
-            if worker.is_ready() {
-                worker.assign(step, duration);
-                return;
-            }
+    Ok(graph)
+}
+
+/// Reads a roster of workers, one per line in the form `NAME SPEED [TAG...]`, where `SPEED`
+/// is how many times faster than normal the worker is. Blank lines and anything following
+/// a `#` are ignored.
+fn read_roster(input: &str) -> Result<Vec<WorkerSpec>> {
+    let mut roster = vec![];
+    for line in input.lines() {
+        let line = line.split('#').next().unwrap();
+        let mut fields = line.split_whitespace();
+        let name = match fields.next() {
+            Some(name) => name,
+            None => continue,
+        };
+
+        let speed: f64 = fields
+            .next()
+            .ok_or_else(|| anyhow!("Missing speed for worker {}", name))?
+            .parse()?;
+        if !speed.is_finite() || speed <= 0.0 {
+            bail!("Worker {} must have a positive speed: {}", name, speed);
         }
 
-        panic!("No available worker");
+        let mut worker = WorkerSpec::new(name);
+        worker.speed = speed;
+        worker.tags = fields.map(String::from).collect();
+        roster.push(worker);
     }
 
-    fn tick(&mut self) {
-        for worker in &mut self.workers {
-            worker.tick();
-        }
+    if roster.is_empty() {
+        bail!("Roster has no workers");
     }
 
-    fn receive(&mut self) -> HashSet<Step> {
-        let mut completed = HashSet::new();
-        for worker in &mut self.workers {
-            if let Some(step) = worker.receive() {
-                completed.insert(step);
-            }
-        }
+    Ok(roster)
+}
 
-        completed
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scheduler::ValidationError;
+
+    const EXAMPLE: &str = "\
+Step C must be finished before step A can begin.
+Step C must be finished before step F can begin.
+Step A must be finished before step B can begin.
+Step A must be finished before step D can begin.
+Step B must be finished before step E can begin.
+Step D must be finished before step E can begin.
+Step F must be finished before step E can begin.
+";
+
+    /// The example with an extra dependency that makes E depend upon C, closing a cycle
+    /// through every step.
+    fn cyclic_example() -> Graph {
+        let input = format!(
+            "{}Step E must be finished before step C can begin.\n",
+            EXAMPLE
+        );
+        read_graph(&input).unwrap()
     }
 
-    fn is_ready(&self) -> bool {
-        for worker in &self.workers {
-            if worker.is_ready() {
-                return true;
-            }
+    fn expected_cycle_error() -> ValidationError {
+        let cycle = |steps: &str| steps.chars().map(String::from).collect();
+        ValidationError {
+            cycles: vec![cycle("ABECA"), cycle("ADECA"), cycle("CFEC")],
+            missing: vec![],
+            unreachable: vec![],
         }
-        false
     }
-}
-
-struct Worker {
-    status: Status,
-}
 
-impl Worker {
-    fn new() -> Worker {
-        Worker {
-            status: Status::Idle,
-        }
+    #[test]
+    fn part1_example() {
+        let graph = read_graph(EXAMPLE).unwrap();
+        let scheduler = Scheduler::new(2, 0);
+        assert_eq!(part1(&graph, &scheduler).unwrap(), "CABDFE");
     }
 
-    fn is_ready(&self) -> bool {
-        self.status.is_idle()
+    #[test]
+    fn part2_example() {
+        let graph = read_graph(EXAMPLE).unwrap();
+        let scheduler = Scheduler::new(2, 0);
+        assert_eq!(part2(&graph, &scheduler).unwrap(), 15);
     }
 
-    fn assign(&mut self, step: Step, duration: u32) {
-        if !self.status.is_idle() {
-            panic!("Worker has incomplete work");
-        }
-        self.status = Status::Working {
-            step,
-            remaining: duration,
-        };
+    #[test]
+    fn part1_rejects_cycle() {
+        let err = part1(&cyclic_example(), &Scheduler::new(2, 0)).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ValidationError>(),
+            Some(&expected_cycle_error())
+        );
     }
 
-    fn receive(&mut self) -> Option<Step> {
-        match self.status {
-            Status::Working { step, remaining } if remaining == 0 => {
-                self.status = Status::Idle;
-                Some(step)
-            }
-            _ => None,
-        }
+    #[test]
+    fn part2_rejects_cycle() {
+        let err = part2(&cyclic_example(), &Scheduler::new(2, 0)).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ValidationError>(),
+            Some(&expected_cycle_error())
+        );
     }
 
-    fn tick(&mut self) {
-        if let Status::Working { step: _, remaining } = &mut self.status {
-            *remaining = remaining.saturating_sub(1);
-        }
+    #[test]
+    fn reports_every_cycle_through_a_step() {
+        let input = "\
+Step A must be finished before step B can begin.
+Step B must be finished before step A can begin.
+Step A must be finished before step C can begin.
+Step C must be finished before step A can begin.
+Step C must be finished before step D can begin.
+";
+        let err = part1(&read_graph(input).unwrap(), &Scheduler::new(2, 0)).unwrap_err();
+        let cycle = |steps: &str| steps.chars().map(String::from).collect();
+        assert_eq!(
+            err.downcast_ref::<ValidationError>(),
+            Some(&ValidationError {
+                cycles: vec![cycle("ABA"), cycle("ACA")],
+                missing: vec![],
+                unreachable: vec!["D".to_string()],
+            })
+        );
     }
-}
 
-#[derive(Clone, Copy, Eq, PartialEq)]
-enum Status {
-    Idle,
-    Working { step: Step, remaining: u32 },
-}
+    #[test]
+    fn part2_respects_worker_affinity() {
+        let input = format!(
+            "{}Step F requires tags rust.\nStep D requires rust, docker.\n",
+            EXAMPLE
+        );
+        let graph = read_graph(&input).unwrap();
+        let roster = read_roster("alice 1\nbob 2 rust docker\n").unwrap();
+        let scheduler = Scheduler::new(0, 0).with_roster(roster);
+
+        let schedule = scheduler.run(&graph).unwrap();
+        let steps = |worker: usize| -> String {
+            schedule.workers[worker]
+                .tasks
+                .iter()
+                .map(|t| t.step.as_str())
+                .collect()
+        };
+        assert_eq!(steps(0), "CABE");
+        assert_eq!(steps(1), "FD");
+        assert_eq!(schedule.makespan, 13);
+    }
 
-impl Status {
-    fn is_idle(&self) -> bool {
-        *self == Status::Idle
+    #[test]
+    fn optimise_beats_greedy() {
+        // Alphabetical greedy scheduling starts A and B first, delaying the long C -> D
+        // chain, whereas starting C straight away finishes two seconds sooner.
+        let input = "\
+Step C must be finished before step D can begin.
+Step A takes 2 seconds.
+Step B takes 2 seconds.
+Step C takes 4 seconds.
+Step D takes 4 seconds.
+";
+        let graph = read_graph(input).unwrap();
+        let scheduler = Scheduler::new(2, 0);
+        assert_eq!(part2(&graph, &scheduler).unwrap(), 10);
+
+        let mut improvements = vec![];
+        let optimised = scheduler
+            .optimise(&graph, Duration::from_secs(10), |makespan, _| {
+                improvements.push(makespan)
+            })
+            .unwrap();
+        assert!(optimised.optimal);
+        assert_eq!(optimised.schedule.makespan, 8);
+        assert_eq!(improvements, vec![8]);
     }
-}
 
-fn read_deps(input: &str) -> Result<DepsByStep> {
-    let pairs: Result<HashSet<DepPair>> = input.lines().map(|v| v.parse()).collect();
-    let pairs = pairs?;
+    #[test]
+    fn rejects_missing_dependency() {
+        let mut graph = read_graph(EXAMPLE).unwrap();
+        graph.add_dep("F", "Z");
 
-    let mut deps = DepsByStep::new();
-    for pair in pairs {
-        deps.entry(pair.0).or_default().insert(pair.1);
-        deps.entry(pair.1).or_default();
+        let err = graph.validate().unwrap_err();
+        assert_eq!(err.missing, vec![("F".to_string(), "Z".to_string())]);
+        assert_eq!(err.unreachable, vec!["E".to_string(), "F".to_string()]);
+        assert!(err.cycles.is_empty());
     }
-
-    Ok(deps)
 }
@@ -0,0 +1,973 @@
+//! Scheduling of the steps of a dependency graph across a pool of workers.
+
+mod optimal;
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use std::{
+    cmp::{self, Reverse},
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque},
+    error::Error,
+    fmt::{self, Display, Write},
+    mem,
+};
+
+pub type StepId = String;
+
+/// Gives the time a step takes on top of the base time, if it can be costed.
+pub type CostFn = dyn Fn(&str) -> Option<u32>;
+
+/// A set of steps along with the steps each one depends upon.
+#[derive(Clone, Debug, Default)]
+pub struct Graph {
+    deps: BTreeMap<StepId, BTreeSet<StepId>>,
+    durations: HashMap<StepId, u32>,
+    required_tags: HashMap<StepId, BTreeSet<String>>,
+}
+
+impl Graph {
+    pub fn new() -> Graph {
+        Default::default()
+    }
+
+    /// Adds `step` to the graph if it isn't already present.
+    pub fn add_step(&mut self, step: &str) {
+        self.deps.entry(step.to_string()).or_default();
+    }
+
+    /// Records that `step` cannot begin until `dep` is finished, adding `step` to the graph
+    /// if it isn't already present. `dep` must be added separately.
+    pub fn add_dep(&mut self, step: &str, dep: &str) {
+        self.deps
+            .entry(step.to_string())
+            .or_default()
+            .insert(dep.to_string());
+    }
+
+    /// Fixes the number of seconds that `step` takes, overriding the scheduler's cost
+    /// function for that step, and adds `step` to the graph if it isn't already present.
+    pub fn set_duration(&mut self, step: &str, duration: u32) {
+        self.add_step(step);
+        self.durations.insert(step.to_string(), duration);
+    }
+
+    /// Records that `step` may only be run by workers with all of `tags`, adding `step` to
+    /// the graph if it isn't already present.
+    pub fn require_tags<'a>(&mut self, step: &str, tags: impl IntoIterator<Item = &'a str>) {
+        self.add_step(step);
+        self.required_tags
+            .entry(step.to_string())
+            .or_default()
+            .extend(tags.into_iter().map(String::from));
+    }
+
+    /// Returns the tags a worker must have to run `step`.
+    pub fn required_tags<'a>(&'a self, step: &'a str) -> impl Iterator<Item = &'a String> {
+        self.required_tags.get(step).into_iter().flatten()
+    }
+
+    pub fn len(&self) -> usize {
+        self.deps.len()
+    }
+
+    /// Returns the steps that `step` depends upon directly.
+    pub fn deps<'a>(&'a self, step: &'a str) -> impl Iterator<Item = &'a StepId> {
+        self.deps.get(step).into_iter().flatten()
+    }
+
+    /// Returns the steps that depend directly upon `step`.
+    pub fn dependents<'a>(&'a self, step: &'a str) -> impl Iterator<Item = &'a StepId> {
+        self.deps
+            .iter()
+            .filter(move |(_, deps)| deps.contains(step))
+            .map(|(s, _)| s)
+    }
+
+    /// Checks that every step of the graph can eventually be run.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let missing: Vec<(StepId, StepId)> = self
+            .deps
+            .iter()
+            .flat_map(|(step, deps)| {
+                deps.iter()
+                    .filter(|dep| !self.deps.contains_key(*dep))
+                    .map(move |dep| (step.clone(), dep.clone()))
+            })
+            .collect();
+
+        let cycles = self.cycles();
+
+        // Steps that can never start are those left over once every runnable step has
+        // been run; any that aren't part of a cycle are blocked by one (or by a missing
+        // dependency) further upstream.
+        let mut blocked: BTreeSet<StepId> = self.deps.keys().cloned().collect();
+        for step in self.runnable_order() {
+            blocked.remove(&step);
+        }
+        for step in cycles.iter().flatten() {
+            blocked.remove(step);
+        }
+        let unreachable: Vec<StepId> = blocked.into_iter().collect();
+
+        if missing.is_empty() && cycles.is_empty() && unreachable.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError {
+                cycles,
+                missing,
+                unreachable,
+            })
+        }
+    }
+
+    /// Returns the steps that can be run in an order that satisfies their dependencies,
+    /// omitting any that are blocked by a cycle or a missing dependency.
+    fn runnable_order(&self) -> Vec<StepId> {
+        let mut remaining: HashMap<&StepId, usize> = self
+            .deps
+            .iter()
+            .map(|(step, deps)| (step, deps.len()))
+            .collect();
+        let mut dependents: HashMap<&StepId, Vec<&StepId>> = HashMap::new();
+        for (step, deps) in &self.deps {
+            for dep in deps {
+                dependents.entry(dep).or_default().push(step);
+            }
+        }
+
+        let mut ready: VecDeque<&StepId> = remaining
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(step, _)| *step)
+            .collect();
+        let mut order = vec![];
+        while let Some(step) = ready.pop_front() {
+            order.push(step.clone());
+            for dependent in dependents.get(step).into_iter().flatten() {
+                let count = remaining.get_mut(dependent).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    ready.push_back(dependent);
+                }
+            }
+        }
+
+        order
+    }
+
+    /// Returns every elementary cycle of steps that depend upon each other, found with
+    /// Johnson's algorithm within each group of mutually dependent steps.
+    ///
+    /// Each cycle is listed in the order the steps would have to finish, beginning and
+    /// ending with its alphabetically first step, and the cycles are sorted.
+    fn cycles(&self) -> Vec<Vec<StepId>> {
+        struct Johnson<'a> {
+            /// The steps that depend upon each step, by index within the component.
+            dependents: &'a [Vec<usize>],
+            /// The step every cycle being searched for begins with. Only steps after it
+            /// are visited, since cycles through earlier steps have already been found.
+            start: usize,
+            blocked: Vec<bool>,
+            /// The steps to unblock once each step is unblocked.
+            blocked_by: Vec<BTreeSet<usize>>,
+            path: Vec<usize>,
+            found: Vec<Vec<usize>>,
+        }
+
+        impl Johnson<'_> {
+            /// Extends the path to `step`, recording every way of getting back to the start
+            /// from there. Returns whether there was any.
+            fn circuit(&mut self, step: usize) -> bool {
+                let (dependents, start) = (self.dependents, self.start);
+                let mut closed = false;
+                self.path.push(step);
+                self.blocked[step] = true;
+
+                for &next in dependents[step].iter().filter(|n| **n >= start) {
+                    if next == start {
+                        let mut cycle = self.path.clone();
+                        cycle.push(start);
+                        self.found.push(cycle);
+                        closed = true;
+                    } else if !self.blocked[next] && self.circuit(next) {
+                        closed = true;
+                    }
+                }
+
+                if closed {
+                    self.unblock(step);
+                } else {
+                    for &next in dependents[step].iter().filter(|n| **n >= start) {
+                        self.blocked_by[next].insert(step);
+                    }
+                }
+
+                self.path.pop();
+                closed
+            }
+
+            fn unblock(&mut self, step: usize) {
+                self.blocked[step] = false;
+                for other in mem::take(&mut self.blocked_by[step]) {
+                    if self.blocked[other] {
+                        self.unblock(other);
+                    }
+                }
+            }
+        }
+
+        let mut cycles = vec![];
+        for component in self.strongly_connected_components() {
+            let members: Vec<&StepId> = component.iter().collect();
+            let index: HashMap<&StepId, usize> =
+                members.iter().enumerate().map(|(i, s)| (*s, i)).collect();
+            let mut dependents = vec![vec![]; members.len()];
+            for (i, step) in members.iter().enumerate() {
+                for dep in self.deps[*step].iter().filter_map(|d| index.get(d)) {
+                    dependents[*dep].push(i);
+                }
+            }
+
+            let mut johnson = Johnson {
+                dependents: &dependents,
+                start: 0,
+                blocked: vec![false; members.len()],
+                blocked_by: vec![BTreeSet::new(); members.len()],
+                path: vec![],
+                found: vec![],
+            };
+            for start in 0..members.len() {
+                johnson.start = start;
+                johnson.blocked.fill(false);
+                johnson.blocked_by.iter_mut().for_each(BTreeSet::clear);
+                johnson.circuit(start);
+            }
+
+            cycles.extend(
+                johnson
+                    .found
+                    .into_iter()
+                    .map(|cycle| cycle.into_iter().map(|i| members[i].clone()).collect()),
+            );
+        }
+        cycles.sort();
+
+        cycles
+    }
+
+    /// Partitions the steps into groups in which every step depends, directly or
+    /// indirectly, upon every other step in the group, using Tarjan's algorithm.
+    fn strongly_connected_components(&self) -> Vec<BTreeSet<StepId>> {
+        struct Tarjan<'a> {
+            graph: &'a Graph,
+            next_index: usize,
+            index: HashMap<&'a StepId, usize>,
+            low_link: HashMap<&'a StepId, usize>,
+            stack: Vec<&'a StepId>,
+            on_stack: HashSet<&'a StepId>,
+            components: Vec<BTreeSet<StepId>>,
+        }
+
+        impl<'a> Tarjan<'a> {
+            fn visit(&mut self, step: &'a StepId) {
+                self.index.insert(step, self.next_index);
+                self.low_link.insert(step, self.next_index);
+                self.next_index += 1;
+                self.stack.push(step);
+                self.on_stack.insert(step);
+
+                let graph = self.graph;
+                for dep in graph.deps[step]
+                    .iter()
+                    .filter(|d| graph.deps.contains_key(*d))
+                {
+                    if !self.index.contains_key(dep) {
+                        self.visit(dep);
+                        let low_link = cmp::min(self.low_link[step], self.low_link[dep]);
+                        self.low_link.insert(step, low_link);
+                    } else if self.on_stack.contains(dep) {
+                        let low_link = cmp::min(self.low_link[step], self.index[dep]);
+                        self.low_link.insert(step, low_link);
+                    }
+                }
+
+                if self.low_link[step] == self.index[step] {
+                    let mut component = BTreeSet::new();
+                    loop {
+                        let member = self.stack.pop().unwrap();
+                        self.on_stack.remove(member);
+                        component.insert(member.clone());
+                        if member == step {
+                            break;
+                        }
+                    }
+                    self.components.push(component);
+                }
+            }
+        }
+
+        let mut tarjan = Tarjan {
+            graph: self,
+            next_index: 0,
+            index: HashMap::new(),
+            low_link: HashMap::new(),
+            stack: vec![],
+            on_stack: HashSet::new(),
+            components: vec![],
+        };
+        for step in self.deps.keys() {
+            if !tarjan.index.contains_key(step) {
+                tarjan.visit(step);
+            }
+        }
+
+        tarjan.components
+    }
+
+    /// Returns the steps that are not finished but whose dependencies all are.
+    fn unlocked<'a>(&'a self, finished: &'a HashSet<StepId>) -> impl Iterator<Item = &'a StepId> {
+        self.deps
+            .iter()
+            .filter(move |(s, deps)| {
+                !finished.contains(*s) && deps.iter().all(|d| finished.contains(d))
+            })
+            .map(|(s, _)| s)
+    }
+}
+
+/// The reasons why some steps of a graph could never be run.
+#[derive(Debug, Eq, PartialEq)]
+pub struct ValidationError {
+    /// Every chain of steps that (indirectly) depends upon itself, listed in the order the
+    /// steps would need to finish and beginning and ending with the same step.
+    pub cycles: Vec<Vec<StepId>>,
+    /// Pairs of a step and a dependency of it that isn't in the graph.
+    pub missing: Vec<(StepId, StepId)>,
+    /// Steps that aren't part of a cycle but depend upon one, or upon a missing step.
+    pub unreachable: Vec<StepId>,
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid dependency graph")?;
+        for cycle in &self.cycles {
+            write!(f, "\n  cycle: {}", cycle.join(" -> "))?;
+        }
+        for (step, dep) in &self.missing {
+            write!(
+                f,
+                "\n  missing: step {} depends on unknown step {}",
+                step, dep
+            )?;
+        }
+        if !self.unreachable.is_empty() {
+            write!(f, "\n  unreachable: {}", self.unreachable.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for ValidationError {}
+
+/// Decides which of the available steps a free worker should begin next.
+pub trait Priority {
+    fn choose<'a>(&self, graph: &Graph, available: &'a BTreeSet<StepId>) -> &'a StepId;
+}
+
+/// Chooses the step that comes first alphabetically.
+pub struct Alphabetical;
+
+impl Priority for Alphabetical {
+    fn choose<'a>(&self, _: &Graph, available: &'a BTreeSet<StepId>) -> &'a StepId {
+        available.iter().max_by(|s1, s2| s2.cmp(s1)).unwrap()
+    }
+}
+
+/// Chooses the step that unblocks the most other steps, breaking ties alphabetically.
+pub struct MostDependents;
+
+impl Priority for MostDependents {
+    fn choose<'a>(&self, graph: &Graph, available: &'a BTreeSet<StepId>) -> &'a StepId {
+        available
+            .iter()
+            .max_by(|s1, s2| {
+                let d1 = graph.dependents(s1).count();
+                let d2 = graph.dependents(s2).count();
+                d1.cmp(&d2).then_with(|| s2.cmp(s1))
+            })
+            .unwrap()
+    }
+}
+
+/// Returns the puzzle's extra cost of a step named by a single letter: 1 second for `A`
+/// through to 26 seconds for `Z`.
+pub fn letter_cost(step: &str) -> Option<u32> {
+    match step.as_bytes() {
+        [c] if c.is_ascii_alphabetic() => Some((c.to_ascii_uppercase() - b'A') as u32 + 1),
+        _ => None,
+    }
+}
+
+/// A worker's name and abilities.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkerSpec {
+    pub name: String,
+    /// How many times faster than normal the worker completes steps.
+    pub speed: f64,
+    /// The skills that the worker has.
+    pub tags: BTreeSet<String>,
+}
+
+impl WorkerSpec {
+    /// Creates a worker of normal speed with no particular skills.
+    pub fn new(name: &str) -> WorkerSpec {
+        WorkerSpec {
+            name: name.to_string(),
+            speed: 1.0,
+            tags: BTreeSet::new(),
+        }
+    }
+
+    /// Returns whether the worker has every tag that `step` requires.
+    pub fn can_run(&self, graph: &Graph, step: &str) -> bool {
+        graph.required_tags(step).all(|tag| self.tags.contains(tag))
+    }
+}
+
+/// Assigns the steps of a graph to workers so that each step begins only after all of its
+/// dependencies are finished.
+pub struct Scheduler {
+    roster: Vec<WorkerSpec>,
+    base_time: u32,
+    cost: Box<CostFn>,
+    priority: Box<dyn Priority>,
+}
+
+impl Scheduler {
+    /// Creates a scheduler with `worker_count` identical workers, in which each step takes
+    /// `base_time` seconds plus its letter cost and ties between available steps are broken
+    /// alphabetically.
+    pub fn new(worker_count: usize, base_time: u32) -> Scheduler {
+        Scheduler {
+            roster: (1..=worker_count)
+                .map(|i| WorkerSpec::new(&format!("Worker {}", i)))
+                .collect(),
+            base_time,
+            cost: Box::new(letter_cost),
+            priority: Box::new(Alphabetical),
+        }
+    }
+
+    /// Replaces the function giving the time each step takes on top of the base time.
+    pub fn with_cost<F>(mut self, cost: F) -> Scheduler
+    where
+        F: Fn(&str) -> Option<u32> + 'static,
+    {
+        self.cost = Box::new(cost);
+        self
+    }
+
+    /// Replaces the workers with those in `roster`.
+    pub fn with_roster(mut self, roster: Vec<WorkerSpec>) -> Scheduler {
+        self.roster = roster;
+        self
+    }
+
+    pub fn with_priority<P>(mut self, priority: P) -> Scheduler
+    where
+        P: Priority + 'static,
+    {
+        self.priority = Box::new(priority);
+        self
+    }
+
+    /// Returns the number of seconds that `step` takes to complete.
+    pub fn duration(&self, graph: &Graph, step: &str) -> Result<u32> {
+        let duration = match graph.durations.get(step) {
+            Some(duration) => *duration,
+            None => {
+                let cost =
+                    (self.cost)(step).ok_or_else(|| anyhow!("No duration for step {}", step))?;
+                self.base_time + cost
+            }
+        };
+
+        if duration == 0 {
+            bail!("Step {} must take at least one second", step);
+        }
+
+        Ok(duration)
+    }
+
+    /// Returns the number of seconds that `worker` takes to complete `step`.
+    pub fn duration_on(&self, graph: &Graph, step: &str, worker: &WorkerSpec) -> Result<u32> {
+        let duration = f64::from(self.duration(graph, step)?) / worker.speed;
+        Ok(cmp::max(1, duration.ceil() as u32))
+    }
+
+    /// Returns the number of seconds that the fastest worker able to run `step` takes.
+    fn fastest_duration(&self, graph: &Graph, step: &str) -> Result<u32> {
+        let mut fastest = None;
+        for worker in self.roster.iter().filter(|w| w.can_run(graph, step)) {
+            let duration = self.duration_on(graph, step, worker)?;
+            fastest = Some(fastest.map_or(duration, |f| cmp::min(f, duration)));
+        }
+
+        fastest.ok_or_else(|| {
+            let tags: Vec<&str> = graph.required_tags(step).map(|t| t.as_str()).collect();
+            anyhow!(
+                "No worker can run step {} (requires tags: {})",
+                step,
+                tags.join(", ")
+            )
+        })
+    }
+
+    /// Returns the order in which a single worker would complete the steps if every step
+    /// finished instantly.
+    pub fn order(&self, graph: &Graph) -> Result<Vec<StepId>> {
+        graph.validate()?;
+
+        let mut finished = HashSet::new();
+        let mut available: BTreeSet<StepId> = graph.unlocked(&finished).cloned().collect();
+
+        let mut order = vec![];
+        while !available.is_empty() {
+            let chosen = self.priority.choose(graph, &available).clone();
+            available.remove(&chosen);
+            finished.insert(chosen.clone());
+            order.push(chosen);
+
+            available.extend(graph.unlocked(&finished).cloned());
+        }
+
+        Ok(order)
+    }
+
+    /// Returns the chain of dependent steps that takes the longest to run one after the
+    /// other on the fastest workers able to run them, along with its total duration. No
+    /// number of workers can finish the graph any sooner than this.
+    pub fn critical_path(&self, graph: &Graph) -> Result<(u32, Vec<StepId>)> {
+        graph.validate()?;
+
+        // The latest finishing time of each step if started as soon as its dependencies
+        // finish, along with the dependency that held it up the longest.
+        let mut finish: HashMap<StepId, (u32, Option<StepId>)> = HashMap::new();
+        for step in graph.runnable_order() {
+            let (start, blocker) = graph
+                .deps(&step)
+                .map(|dep| (finish[dep].0, Some(dep.clone())))
+                .max()
+                .unwrap_or((0, None));
+            let duration = self.fastest_duration(graph, &step)?;
+            finish.insert(step, (start + duration, blocker));
+        }
+
+        let last = finish
+            .iter()
+            .max_by(|(s1, (f1, _)), (s2, (f2, _))| f1.cmp(f2).then_with(|| s2.cmp(s1)));
+        let (length, mut step) = match last {
+            Some((step, (length, _))) => (*length, Some(step.clone())),
+            None => return Ok((0, vec![])),
+        };
+
+        let mut path = vec![];
+        while let Some(s) = step {
+            step = finish[&s].1.clone();
+            path.push(s);
+        }
+        path.reverse();
+
+        Ok((length, path))
+    }
+
+    /// Runs every step of `graph`, returning when and by which worker each was run.
+    pub fn run(&self, graph: &Graph) -> Result<Schedule> {
+        self.run_with(graph, WorkerPool::new(self.roster.len()))
+    }
+
+    fn run_with<P: Pool>(&self, graph: &Graph, mut worker_pool: P) -> Result<Schedule> {
+        let (critical_path_length, critical_path) = self.critical_path(graph)?;
+
+        let mut workers: Vec<WorkerTasks> = self
+            .roster
+            .iter()
+            .map(|w| WorkerTasks {
+                name: w.name.clone(),
+                tasks: vec![],
+            })
+            .collect();
+        let mut finished = HashSet::new();
+        let mut assigned = HashSet::new();
+        let mut available: BTreeSet<StepId> = graph.unlocked(&finished).cloned().collect();
+
+        let mut seconds = 0;
+        while finished.len() < graph.len() {
+            // Only steps that one of the idle workers is able to run can be started now.
+            // The chosen step goes to the first idle worker that can run it.
+            loop {
+                let idle = worker_pool.idle_workers();
+                let can_run =
+                    |step: &str| idle.iter().any(|w| self.roster[*w].can_run(graph, step));
+                let runnable: BTreeSet<StepId> =
+                    available.iter().filter(|s| can_run(s)).cloned().collect();
+                if runnable.is_empty() {
+                    break;
+                }
+
+                let chosen = self.priority.choose(graph, &runnable).clone();
+                let worker = *idle
+                    .iter()
+                    .find(|w| self.roster[**w].can_run(graph, &chosen))
+                    .unwrap();
+                let duration = self.duration_on(graph, &chosen, &self.roster[worker])?;
+                available.remove(&chosen);
+                assigned.insert(chosen.clone());
+                worker_pool.assign(worker, chosen.clone(), duration);
+                workers[worker].tasks.push(Task {
+                    step: chosen,
+                    start: seconds,
+                    end: seconds + duration,
+                });
+            }
+
+            let (now, batch_finished) = worker_pool.advance();
+            seconds = now;
+            for step in &batch_finished {
+                assigned.remove(step);
+            }
+            finished.extend(batch_finished);
+
+            available.extend(
+                graph
+                    .unlocked(&finished)
+                    .filter(|s| !assigned.contains(*s))
+                    .cloned(),
+            );
+        }
+
+        Ok(Schedule {
+            workers,
+            makespan: seconds,
+            critical_path,
+            critical_path_length,
+        })
+    }
+}
+
+/// A step carried out by a worker and the time it took.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Task {
+    pub step: StepId,
+    pub start: u32,
+    pub end: u32,
+}
+
+/// The tasks carried out by a single worker, in the order they were started.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct WorkerTasks {
+    pub name: String,
+    pub tasks: Vec<Task>,
+}
+
+/// The result of running every step of a graph.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Schedule {
+    pub workers: Vec<WorkerTasks>,
+    /// The time at which the last step finished.
+    pub makespan: u32,
+    /// The chain of dependent steps that takes the longest to run.
+    pub critical_path: Vec<StepId>,
+    /// The total duration of the steps on the critical path.
+    pub critical_path_length: u32,
+}
+
+impl Schedule {
+    /// Renders the schedule as a table with one row per second, in the style of the
+    /// puzzle's example, followed by the critical path.
+    pub fn to_gantt(&self) -> String {
+        let mut done: Vec<(u32, usize, &StepId)> = self
+            .workers
+            .iter()
+            .enumerate()
+            .flat_map(|(i, worker)| worker.tasks.iter().map(move |t| (t.end, i, &t.step)))
+            .collect();
+        done.sort();
+
+        let single_chars = done.iter().all(|(_, _, step)| step.chars().count() == 1);
+        let separator = if single_chars { "" } else { " " };
+        let width = self
+            .workers
+            .iter()
+            .map(|worker| worker.name.len())
+            .chain(done.iter().map(|(_, _, step)| step.len()))
+            .max()
+            .unwrap_or(0);
+        let second_width = cmp::max("Second".len(), self.makespan.to_string().len());
+
+        let mut out = String::new();
+        write!(out, "{:>w$}", "Second", w = second_width).unwrap();
+        for worker in &self.workers {
+            write!(out, "   {:^w$}", worker.name, w = width).unwrap();
+        }
+        out.push_str("   Done\n");
+
+        for second in 0..=self.makespan {
+            let mut row = format!("{:>w$}", second, w = second_width);
+            for worker in &self.workers {
+                let step = worker
+                    .tasks
+                    .iter()
+                    .find(|t| t.start <= second && second < t.end)
+                    .map_or(".", |t| t.step.as_str());
+                write!(row, "   {:^w$}", step, w = width).unwrap();
+            }
+
+            let finished: Vec<&str> = done
+                .iter()
+                .take_while(|(end, _, _)| *end <= second)
+                .map(|(_, _, step)| step.as_str())
+                .collect();
+            write!(row, "   {}", finished.join(separator)).unwrap();
+            writeln!(out, "{}", row.trim_end()).unwrap();
+        }
+
+        writeln!(
+            out,
+            "\nCritical path ({} seconds): {}",
+            self.critical_path_length,
+            self.critical_path.join(" -> ")
+        )
+        .unwrap();
+
+        out
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Workers that steps can be handed to and that report back when they finish them.
+trait Pool {
+    /// Returns the indexes of the workers that are not running a step, in ascending order.
+    fn idle_workers(&self) -> Vec<usize>;
+
+    /// Starts `step` on the idle worker with index `worker` at the current time.
+    fn assign(&mut self, worker: usize, step: StepId, duration: u32);
+
+    /// Moves time forward until at least one step is finished, returning the new time and
+    /// every step that finished at that time.
+    fn advance(&mut self) -> (u32, HashSet<StepId>);
+}
+
+/// A pool of workers that tracks when each busy worker will finish, so that time can jump
+/// straight from one completion to the next.
+struct WorkerPool {
+    now: u32,
+    idle: BTreeSet<usize>,
+    busy: BinaryHeap<Reverse<(u32, usize, StepId)>>,
+}
+
+impl WorkerPool {
+    fn new(size: usize) -> WorkerPool {
+        WorkerPool {
+            now: 0,
+            idle: (0..size).collect(),
+            busy: BinaryHeap::new(),
+        }
+    }
+}
+
+impl Pool for WorkerPool {
+    fn idle_workers(&self) -> Vec<usize> {
+        self.idle.iter().copied().collect()
+    }
+
+    fn assign(&mut self, worker: usize, step: StepId, duration: u32) {
+        if !self.idle.remove(&worker) {
+            panic!("Worker has incomplete work");
+        }
+        self.busy.push(Reverse((self.now + duration, worker, step)));
+    }
+
+    fn advance(&mut self) -> (u32, HashSet<StepId>) {
+        let Reverse((end, _, _)) = self.busy.peek().expect("No assigned work");
+        self.now = *end;
+
+        let mut finished = HashSet::new();
+        while let Some(Reverse((end, worker, step))) = self.busy.peek().cloned() {
+            if end != self.now {
+                break;
+            }
+            self.busy.pop();
+            self.idle.insert(worker);
+            finished.insert(step);
+        }
+
+        (self.now, finished)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// The original simulation, which steps through time one second at a time.
+    mod tick {
+        use super::super::{Pool, StepId};
+        use std::collections::HashSet;
+
+        pub struct WorkerPool {
+            seconds: u32,
+            workers: Vec<Worker>,
+        }
+
+        impl WorkerPool {
+            pub fn new(size: usize) -> WorkerPool {
+                let mut worker_pool = WorkerPool {
+                    seconds: 0,
+                    workers: vec![],
+                };
+                for _ in 0..size {
+                    worker_pool.workers.push(Worker::new());
+                }
+                worker_pool
+            }
+
+            fn tick(&mut self) {
+                for worker in &mut self.workers {
+                    worker.tick();
+                }
+            }
+
+            fn receive(&mut self) -> HashSet<StepId> {
+                let mut completed = HashSet::new();
+                for worker in &mut self.workers {
+                    if let Some(step) = worker.receive() {
+                        completed.insert(step);
+                    }
+                }
+
+                completed
+            }
+        }
+
+        impl Pool for WorkerPool {
+            fn idle_workers(&self) -> Vec<usize> {
+                (0..self.workers.len())
+                    .filter(|i| self.workers[*i].is_ready())
+                    .collect()
+            }
+
+            fn assign(&mut self, worker: usize, step: StepId, duration: u32) {
+                self.workers[worker].assign(step, duration);
+            }
+
+            fn advance(&mut self) -> (u32, HashSet<StepId>) {
+                loop {
+                    self.tick();
+                    self.seconds += 1;
+                    let finished = self.receive();
+                    if !finished.is_empty() {
+                        return (self.seconds, finished);
+                    }
+                }
+            }
+        }
+
+        struct Worker {
+            status: Status,
+        }
+
+        impl Worker {
+            fn new() -> Worker {
+                Worker {
+                    status: Status::Idle,
+                }
+            }
+
+            fn is_ready(&self) -> bool {
+                self.status.is_idle()
+            }
+
+            fn assign(&mut self, step: StepId, duration: u32) {
+                if !self.status.is_idle() {
+                    panic!("Worker has incomplete work");
+                }
+                self.status = Status::Working {
+                    step,
+                    remaining: duration,
+                };
+            }
+
+            fn receive(&mut self) -> Option<StepId> {
+                match &self.status {
+                    Status::Working { remaining: 0, .. } => {
+                        match std::mem::replace(&mut self.status, Status::Idle) {
+                            Status::Working { step, .. } => Some(step),
+                            Status::Idle => unreachable!(),
+                        }
+                    }
+                    _ => None,
+                }
+            }
+
+            fn tick(&mut self) {
+                if let Status::Working { step: _, remaining } = &mut self.status {
+                    *remaining = remaining.saturating_sub(1);
+                }
+            }
+        }
+
+        #[derive(Clone, Eq, PartialEq)]
+        enum Status {
+            Idle,
+            Working { step: StepId, remaining: u32 },
+        }
+
+        impl Status {
+            fn is_idle(&self) -> bool {
+                *self == Status::Idle
+            }
+        }
+    }
+
+    /// Generates a random acyclic graph using a fixed seed, along with random durations.
+    fn random_graph(seed: u64, size: usize) -> Graph {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let steps: Vec<String> = (0..size).map(|i| format!("S{:03}", i)).collect();
+        let mut graph = Graph::new();
+        for (i, step) in steps.iter().enumerate() {
+            graph.set_duration(step, rng.gen_range(1..=100));
+            for _ in 0..rng.gen_range(0..4) {
+                if i > 0 {
+                    graph.add_dep(step, &steps[rng.gen_range(0..i)]);
+                }
+            }
+        }
+
+        graph
+    }
+
+    #[test]
+    fn event_driven_matches_ticking() {
+        for seed in 0..50 {
+            let graph = random_graph(seed, 40);
+            for worker_count in 1..=6 {
+                let scheduler = Scheduler::new(worker_count, 0);
+                let ticking = scheduler
+                    .run_with(&graph, tick::WorkerPool::new(worker_count))
+                    .unwrap();
+                let event_driven = scheduler.run(&graph).unwrap();
+                assert_eq!(
+                    event_driven, ticking,
+                    "seed {} with {} workers",
+                    seed, worker_count
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,395 @@
+//! Branch-and-bound search for the schedule with the smallest makespan.
+
+use super::{Graph, Schedule, Scheduler, StepId, Task, WorkerTasks};
+use anyhow::Result;
+use std::{
+    cmp,
+    collections::{BTreeSet, HashMap},
+    time::{Duration, Instant},
+};
+
+/// How often (in search nodes) to check whether the time budget has run out.
+const BUDGET_CHECK_INTERVAL: u64 = 1024;
+
+/// A task started during the search, as `(worker, step, start, end)`.
+type TaskRecord = (usize, usize, u32, u32);
+
+/// The finished steps, and the step each worker is running with the time it has left.
+type StateKey = (Vec<bool>, Vec<Option<(usize, u32)>>);
+
+/// The best schedule found by [`Scheduler::optimise`].
+#[derive(Debug)]
+pub struct Optimised {
+    pub schedule: Schedule,
+    /// Whether the search finished, proving that no schedule can be any shorter.
+    pub optimal: bool,
+    /// The number of search nodes visited.
+    pub nodes: u64,
+}
+
+impl Scheduler {
+    /// Searches for the schedule of `graph` that finishes soonest, for the same workers,
+    /// durations and dependencies as [`Scheduler::run`] but free to choose any step (or
+    /// none) for each idle worker.
+    ///
+    /// Starting from the greedy schedule, the makespan of each better schedule found is
+    /// passed to `on_improvement` along with the time spent searching so far. If `budget`
+    /// runs out before the search completes, the best schedule found so far is returned.
+    pub fn optimise<F>(
+        &self,
+        graph: &Graph,
+        budget: Duration,
+        on_improvement: F,
+    ) -> Result<Optimised>
+    where
+        F: FnMut(u32, Duration),
+    {
+        let greedy = self.run(graph)?;
+        let (critical_path_length, critical_path) = self.critical_path(graph)?;
+        let problem = Problem::new(self, graph)?;
+
+        let mut search = Search {
+            problem: &problem,
+            started: Instant::now(),
+            budget,
+            on_improvement,
+            best_makespan: greedy.makespan,
+            best_tasks: None,
+            seen: HashMap::new(),
+            nodes: 0,
+            timed_out: false,
+        };
+
+        let mut node = Node {
+            time: 0,
+            finished: vec![false; problem.steps.len()],
+            started: vec![false; problem.steps.len()],
+            running: vec![None; problem.workers.len()],
+            tasks: vec![],
+        };
+        search.explore(&mut node);
+
+        let (nodes, timed_out) = (search.nodes, search.timed_out);
+        let schedule = match search.best_tasks {
+            Some((makespan, tasks)) => {
+                let mut workers: Vec<WorkerTasks> = self
+                    .roster
+                    .iter()
+                    .map(|w| WorkerTasks {
+                        name: w.name.clone(),
+                        tasks: vec![],
+                    })
+                    .collect();
+                for (worker, step, start, end) in tasks {
+                    workers[worker].tasks.push(Task {
+                        step: problem.steps[step].clone(),
+                        start,
+                        end,
+                    });
+                }
+
+                Schedule {
+                    workers,
+                    makespan,
+                    critical_path,
+                    critical_path_length,
+                }
+            }
+            None => greedy,
+        };
+
+        Ok(Optimised {
+            schedule,
+            optimal: !timed_out,
+            nodes,
+        })
+    }
+}
+
+/// The graph and roster reduced to indexes for fast searching.
+struct Problem {
+    steps: Vec<StepId>,
+    deps: Vec<Vec<usize>>,
+    /// The steps in the order the scheduler's priority policy would prefer them.
+    preferred: Vec<usize>,
+    /// The number of seconds each worker takes for each step, if it can run it at all.
+    durations: Vec<Vec<Option<u32>>>,
+    /// The fastest any worker can run each step.
+    fastest: Vec<u32>,
+    /// The longest chain of fastest durations from the start of each step to the end of
+    /// the graph, including the step itself.
+    tail: Vec<u32>,
+    /// The workers, identified by a key shared only by workers with the same abilities.
+    workers: Vec<usize>,
+}
+
+impl Problem {
+    fn new(scheduler: &Scheduler, graph: &Graph) -> Result<Problem> {
+        let order = graph.runnable_order();
+        let index: HashMap<&StepId, usize> =
+            order.iter().enumerate().map(|(i, s)| (s, i)).collect();
+        let deps: Vec<Vec<usize>> = order
+            .iter()
+            .map(|step| graph.deps(step).map(|d| index[d]).collect())
+            .collect();
+
+        let mut preferred = vec![];
+        let mut remaining: BTreeSet<StepId> = order.iter().cloned().collect();
+        while !remaining.is_empty() {
+            let chosen = scheduler.priority.choose(graph, &remaining).clone();
+            preferred.push(index[&chosen]);
+            remaining.remove(&chosen);
+        }
+
+        let mut durations = vec![];
+        for worker in &scheduler.roster {
+            let mut worker_durations = vec![];
+            for step in &order {
+                worker_durations.push(if worker.can_run(graph, step) {
+                    Some(scheduler.duration_on(graph, step, worker)?)
+                } else {
+                    None
+                });
+            }
+            durations.push(worker_durations);
+        }
+
+        let mut fastest = vec![];
+        for step in &order {
+            fastest.push(scheduler.fastest_duration(graph, step)?);
+        }
+
+        // Steps are in dependency order, so walking backwards visits each step after all of
+        // the steps that depend on it.
+        let mut tail = fastest.clone();
+        for step in (0..order.len()).rev() {
+            for dep in &deps[step] {
+                tail[*dep] = cmp::max(tail[*dep], fastest[*dep] + tail[step]);
+            }
+        }
+
+        let mut kinds: Vec<(u64, &BTreeSet<String>)> = vec![];
+        let workers = scheduler
+            .roster
+            .iter()
+            .map(|w| {
+                let kind = (w.speed.to_bits(), &w.tags);
+                match kinds.iter().position(|k| *k == kind) {
+                    Some(i) => i,
+                    None => {
+                        kinds.push(kind);
+                        kinds.len() - 1
+                    }
+                }
+            })
+            .collect();
+
+        Ok(Problem {
+            steps: order,
+            deps,
+            preferred,
+            durations,
+            fastest,
+            tail,
+            workers,
+        })
+    }
+}
+
+/// A point in the search: the work finished and in progress at some time.
+struct Node {
+    time: u32,
+    finished: Vec<bool>,
+    started: Vec<bool>,
+    /// The step each worker is running and when it will end.
+    running: Vec<Option<(usize, u32)>>,
+    /// Every task started so far.
+    tasks: Vec<TaskRecord>,
+}
+
+struct Search<'a, F> {
+    problem: &'a Problem,
+    started: Instant,
+    budget: Duration,
+    on_improvement: F,
+    best_makespan: u32,
+    best_tasks: Option<(u32, Vec<TaskRecord>)>,
+    /// The earliest time at which each combination of finished and running work has been
+    /// reached. Reaching it again any later can't lead to a better schedule.
+    seen: HashMap<StateKey, u32>,
+    nodes: u64,
+    timed_out: bool,
+}
+
+impl<'a, F> Search<'a, F>
+where
+    F: FnMut(u32, Duration),
+{
+    /// Explores every way of continuing from `node`, which sits at a point in time where
+    /// workers may have just become idle.
+    fn explore(&mut self, node: &mut Node) {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(BUDGET_CHECK_INTERVAL) && self.started.elapsed() > self.budget
+        {
+            self.timed_out = true;
+        }
+        if self.timed_out {
+            return;
+        }
+
+        if node.finished.iter().all(|f| *f) {
+            if node.time < self.best_makespan {
+                self.best_makespan = node.time;
+                self.best_tasks = Some((node.time, node.tasks.clone()));
+                (self.on_improvement)(node.time, self.started.elapsed());
+            }
+            return;
+        }
+
+        if self.lower_bound(node) >= self.best_makespan {
+            return;
+        }
+
+        let key = (
+            node.finished.clone(),
+            node.running
+                .iter()
+                .map(|r| r.map(|(step, end)| (step, end - node.time)))
+                .collect(),
+        );
+        match self.seen.get(&key) {
+            Some(time) if *time <= node.time => return,
+            _ => {
+                self.seen.insert(key, node.time);
+            }
+        }
+
+        self.decide(node, 0, &mut vec![None; self.problem.workers.len()]);
+    }
+
+    /// Chooses what each idle worker from `worker` onwards does, then moves on to the next
+    /// time that a step finishes. `choices` holds the decisions already made at this time
+    /// (`Some(None)` meaning the worker was left idle) so that workers with the same
+    /// abilities are only ever considered in one canonical order.
+    fn decide(&mut self, node: &mut Node, worker: usize, choices: &mut [Option<Option<usize>>]) {
+        if self.timed_out {
+            return;
+        }
+
+        let problem = self.problem;
+        if worker == problem.workers.len() {
+            self.advance(node);
+            return;
+        }
+        if node.running[worker].is_some() {
+            self.decide(node, worker + 1, choices);
+            return;
+        }
+
+        // The most recent decision made for an idle worker of the same kind. Steps are
+        // handed to such workers in ascending order, with any left idle coming last.
+        let previous = (0..worker)
+            .rev()
+            .filter(|w| problem.workers[*w] == problem.workers[worker])
+            .find_map(|w| choices[w]);
+
+        if previous != Some(None) {
+            for &step in &problem.preferred {
+                let duration = match problem.durations[worker][step] {
+                    Some(duration) => duration,
+                    None => continue,
+                };
+                if node.started[step] || !problem.deps[step].iter().all(|d| node.finished[*d]) {
+                    continue;
+                }
+                if let Some(Some(previous_step)) = previous {
+                    if step <= previous_step {
+                        continue;
+                    }
+                }
+
+                let end = node.time + duration;
+                node.started[step] = true;
+                node.running[worker] = Some((step, end));
+                node.tasks.push((worker, step, node.time, end));
+                choices[worker] = Some(Some(step));
+
+                self.decide(node, worker + 1, choices);
+
+                choices[worker] = None;
+                node.tasks.pop();
+                node.running[worker] = None;
+                node.started[step] = false;
+            }
+        }
+
+        choices[worker] = Some(None);
+        self.decide(node, worker + 1, choices);
+        choices[worker] = None;
+    }
+
+    /// Moves `node` forward to the next time that a step finishes and explores from there.
+    fn advance(&mut self, node: &mut Node) {
+        let next = match node.running.iter().flatten().map(|(_, end)| *end).min() {
+            Some(next) => next,
+            // Every worker was left idle with nothing running, which leads nowhere.
+            None => return,
+        };
+
+        let previous_time = node.time;
+        let mut completed = vec![];
+        for (worker, running) in node.running.iter_mut().enumerate() {
+            if let Some((step, end)) = *running {
+                if end == next {
+                    completed.push((worker, step, end));
+                    node.finished[step] = true;
+                    *running = None;
+                }
+            }
+        }
+        node.time = next;
+
+        self.explore(node);
+
+        node.time = previous_time;
+        for (worker, step, end) in completed {
+            node.finished[step] = false;
+            node.running[worker] = Some((step, end));
+        }
+    }
+
+    /// Returns a time that no schedule continuing from `node` can finish before.
+    fn lower_bound(&self, node: &Node) -> u32 {
+        let problem = self.problem;
+
+        // Work in progress must end, and then everything depending on it must still run.
+        let mut bound = node.time;
+        for (step, end) in node.running.iter().flatten() {
+            bound = cmp::max(bound, end + problem.tail[*step] - problem.fastest[*step]);
+        }
+
+        // Steps whose dependencies are all finished can start no sooner than now.
+        let mut remaining_work = 0u64;
+        for step in 0..problem.steps.len() {
+            if node.started[step] {
+                continue;
+            }
+            remaining_work += u64::from(problem.fastest[step]);
+            if problem.deps[step].iter().all(|d| node.finished[*d]) {
+                bound = cmp::max(bound, node.time + problem.tail[step]);
+            }
+        }
+
+        // The workers can't share out the remaining work any more evenly than equally.
+        let busy_work: u64 = node
+            .running
+            .iter()
+            .flatten()
+            .map(|(_, end)| u64::from(end - node.time))
+            .sum();
+        let worker_count = problem.workers.len() as u64;
+        let load_bound = u64::from(node.time) + (remaining_work + busy_work).div_ceil(worker_count);
+
+        cmp::max(bound, load_bound as u32)
+    }
+}
@@ -1,22 +1,91 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use std::{
-    collections::VecDeque,
-    io::{self, Read},
+    env,
+    fmt::Write,
+    io::{self, BufRead},
+    mem,
+    str::FromStr,
 };
 
 fn main() -> Result<()> {
-    let mut input = String::new();
-    io::stdin().read_to_string(&mut input)?;
+    let Options { mode, strict } = Options::from_args(env::args().skip(1))?;
+    let read_tree = || build_tree(Numbers::new(io::stdin().lock()), strict);
+    match mode {
+        Mode::Solve => {
+            let root = read_tree()?;
+            println!("Answer to part 1 is {}", root.value1());
+            println!("Answer to part 2 is {}", root.value2());
+        }
+        Mode::Tree => print!("{}", read_tree()?.pretty("root")),
+        Mode::Query(path) => {
+            let root = read_tree()?;
+            let node = root
+                .at(&parse_path(&path)?)
+                .with_context(|| format!("no node at path {}", path))?;
+            print!("{}", node.pretty(&path));
+        }
+        Mode::Serialise => println!("{}", read_tree()?.serialise()),
+        Mode::Generate(spec) => println!("{}", spec.generate()?.serialise()),
+    }
 
-    let values: Result<VecDeque<u32>, _> =
-        input.trim().split(' ').map(|v| v.parse::<u32>()).collect();
-    let mut values = values?;
-    let root = build_tree(&mut values)?;
+    Ok(())
+}
 
-    println!("Answer to part 1 is {}", root.value1());
-    println!("Answer to part 2 is {}", root.value2());
+/// Selects what the program does with the licence tree.
+enum Mode {
+    /// Print the answers to both parts of the puzzle.
+    Solve,
+    /// Print the tree read from stdin with the values of every node.
+    Tree,
+    /// Print the subtree at a path such as `0/2/1` of the tree read from stdin.
+    Query(String),
+    /// Print the tree read from stdin back out in the flat licence format.
+    Serialise,
+    /// Print a randomly generated tree in the flat licence format.
+    Generate(Spec),
+}
 
-    Ok(())
+struct Options {
+    mode: Mode,
+    /// Whether to reject input with numbers left over after the root node.
+    strict: bool,
+}
+
+impl Options {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Options> {
+        let mut mode = None;
+        let mut strict = false;
+        while let Some(arg) = args.next() {
+            let selected = match arg.as_str() {
+                "--strict" => {
+                    strict = true;
+                    continue;
+                }
+                "--tree" => Mode::Tree,
+                "--query" => Mode::Query(args.next().context("Missing path for --query")?),
+                "--serialise" => Mode::Serialise,
+                "--generate" => Mode::Generate(
+                    args.next()
+                        .context("Missing spec for --generate")?
+                        .parse()?,
+                ),
+                _ => bail!(
+                    "Usage: day08 [--strict] [--tree | --query PATH | --serialise | --generate \
+                     DEPTH,CHILDREN,ENTRIES[,SEED]] (got {})",
+                    arg
+                ),
+            };
+            if mode.replace(selected).is_some() {
+                bail!("Unexpected argument: {}", arg);
+            }
+        }
+
+        Ok(Options {
+            mode: mode.unwrap_or(Mode::Solve),
+            strict,
+        })
+    }
 }
 
 struct Node {
@@ -33,51 +102,589 @@ impl Node {
     }
 
     fn value1(&self) -> u32 {
-        self.children.iter().fold(0, |acc, n| acc + n.value1()) + self.entries.iter().sum::<u32>()
+        let mut value = 0;
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            value += node.entries.iter().sum::<u32>();
+            stack.extend(&node.children);
+        }
+
+        value
     }
 
     fn value2(&self) -> u32 {
-        if self.children.is_empty() {
-            return self.entries.iter().sum();
+        // Walk the tree in post-order, leaving the values of each node's children on
+        // `values` (in order) by the time the node itself is revisited.
+        let mut values: Vec<u32> = vec![];
+        let mut stack = vec![(self, false)];
+        while let Some((node, visited)) = stack.pop() {
+            if node.children.is_empty() {
+                values.push(node.entries.iter().sum());
+            } else if !visited {
+                stack.push((node, true));
+                stack.extend(node.children.iter().rev().map(|child| (child, false)));
+            } else {
+                let children = values.split_off(values.len() - node.children.len());
+                values.push(node.value2_from(&children));
+            }
         }
 
+        values.pop().unwrap_or(0)
+    }
+
+    /// Calculates the second value of a node with children given their second values.
+    fn value2_from(&self, children: &[u32]) -> u32 {
         let mut value = 0;
         for entry in &self.entries {
             if *entry > 0 {
                 let index = entry.saturating_sub(1) as usize;
-                value += self.children.get(index).map(|n| n.value2()).unwrap_or(0);
+                value += children.get(index).copied().unwrap_or(0);
             }
         }
 
         value
     }
+
+    /// Returns every node in the tree in pre-order, paired with its depth below this
+    /// node and its index amongst its siblings.
+    fn preorder(&self) -> Vec<(&Node, usize, usize)> {
+        let mut nodes = vec![];
+        let mut stack = vec![(self, 0, 0)];
+        while let Some((node, depth, index)) = stack.pop() {
+            nodes.push((node, depth, index));
+            stack.extend(
+                node.children
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(i, child)| (child, depth + 1, i)),
+            );
+        }
+
+        nodes
+    }
+
+    /// Calculates both values of every node in the tree, in pre-order.
+    fn values(&self) -> Vec<(u32, u32)> {
+        let nodes = self.preorder();
+
+        // In reverse pre-order each subtree reduces to the values of its root, with the
+        // first child's values on top of the stack by the time the parent is reached.
+        let mut values = vec![(0, 0); nodes.len()];
+        let mut stack: Vec<(u32, u32)> = vec![];
+        for (i, (node, _, _)) in nodes.iter().enumerate().rev() {
+            let children: Vec<(u32, u32)> = stack
+                .drain(stack.len() - node.children.len()..)
+                .rev()
+                .collect();
+            let entries: u32 = node.entries.iter().sum();
+            let value1 = entries + children.iter().map(|(v1, _)| v1).sum::<u32>();
+            let value2 = if children.is_empty() {
+                entries
+            } else {
+                node.value2_from(&children.iter().map(|(_, v2)| *v2).collect::<Vec<_>>())
+            };
+            values[i] = (value1, value2);
+            stack.push(values[i]);
+        }
+
+        values
+    }
+
+    /// Finds the node reached by following the child indexes in `path`.
+    fn at(&self, path: &[usize]) -> Option<&Node> {
+        let mut node = self;
+        for index in path {
+            node = node.children.get(*index)?;
+        }
+
+        Some(node)
+    }
+
+    /// Writes the tree back out in the flat header/metadata format it was read from.
+    fn serialise(&self) -> String {
+        enum Visit<'a> {
+            Enter(&'a Node),
+            Exit(&'a Node),
+        }
+
+        let mut numbers = vec![];
+        let mut stack = vec![Visit::Enter(self)];
+        while let Some(visit) = stack.pop() {
+            match visit {
+                Visit::Enter(node) => {
+                    numbers.push(node.children.len() as u32);
+                    numbers.push(node.entries.len() as u32);
+                    stack.push(Visit::Exit(node));
+                    stack.extend(node.children.iter().rev().map(Visit::Enter));
+                }
+                Visit::Exit(node) => numbers.extend(&node.entries),
+            }
+        }
+
+        numbers
+            .iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Renders the tree indented by depth, with the values of every node. The root is
+    /// labelled `label` and every other node with its index amongst its siblings.
+    fn pretty(&self, label: &str) -> String {
+        let mut output = String::new();
+        let values = self.values();
+        for (i, (node, depth, index)) in self.preorder().into_iter().enumerate() {
+            let (value1, value2) = values[i];
+            let entries: Vec<String> = node.entries.iter().map(|e| e.to_string()).collect();
+            write!(output, "{:1$}", "", depth * 2).unwrap();
+            if depth == 0 {
+                write!(output, "{}", label).unwrap();
+            } else {
+                write!(output, "{}", index).unwrap();
+            }
+            writeln!(
+                output,
+                ": {} {}, entries [{}], value1 {}, value2 {}",
+                node.children.len(),
+                if node.children.len() == 1 {
+                    "child"
+                } else {
+                    "children"
+                },
+                entries.join(" "),
+                value1,
+                value2
+            )
+            .unwrap();
+        }
+
+        output
+    }
+}
+
+/// Parses a path of child indexes such as `0/2/1`, where an empty path is the root.
+fn parse_path(path: &str) -> Result<Vec<usize>> {
+    let path = path.trim_matches('/');
+    if path.is_empty() {
+        return Ok(vec![]);
+    }
+
+    path.split('/')
+        .map(|index| {
+            index
+                .parse()
+                .with_context(|| format!("invalid child index {:?} in path", index))
+        })
+        .collect()
+}
+
+/// Describes the shape of a randomly generated tree, written as
+/// `DEPTH,CHILDREN,ENTRIES[,SEED]`.
+#[derive(Clone, Copy, Debug)]
+struct Spec {
+    /// How many levels of nodes there are below the root, at most.
+    depth: usize,
+    /// The most children any node has.
+    children: u32,
+    /// The most metadata entries any node has. Every node has at least one.
+    entries: u32,
+    seed: u64,
+}
+
+impl FromStr for Spec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Spec> {
+        let parts = s
+            .split(',')
+            .map(|part| part.trim().parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("invalid tree spec {:?}", s))?;
+        let (depth, children, entries, seed) = match parts[..] {
+            [depth, children, entries] => (depth, children, entries, 0),
+            [depth, children, entries, seed] => (depth, children, entries, seed),
+            _ => bail!(
+                "expected tree spec DEPTH,CHILDREN,ENTRIES[,SEED] but got {:?}",
+                s
+            ),
+        };
+        if entries == 0 {
+            bail!("nodes need at least one metadata entry");
+        }
+
+        let spec = Spec {
+            depth: depth as usize,
+            children: u32::try_from(children)?,
+            entries: u32::try_from(entries)?,
+            seed,
+        };
+        spec.child_range()?;
+        Ok(spec)
+    }
 }
 
-fn build_tree(values: &mut VecDeque<u32>) -> Result<Node> {
-    let total_children = values
-        .pop_front()
-        .context("expected header specifying number of child nodes")?;
-    let total_entries = values
-        .pop_front()
-        .context("expected header specifying number of metadata entries")?
-        as usize;
+impl Spec {
+    /// The number of child counts a node can have, from none up to `children`.
+    fn child_range(&self) -> Result<u32> {
+        self.children
+            .checked_add(1)
+            .with_context(|| format!("too many children for a tree spec: {}", self.children))
+    }
+
+    /// Generates a tree of this shape. Metadata entries range from 1 to one past the
+    /// number of children, so some refer to children that don't exist.
+    fn generate(&self) -> Result<Node> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        // Generate the flat format directly, keeping the headers still to be closed off
+        // with their entries as `(remaining children, children, entries, depth)`.
+        let child_range = self.child_range()?;
+        let mut numbers = vec![];
+        let mut stack: Vec<(u32, u32, u32, usize)> = vec![];
+        let mut depth = 0;
+        loop {
+            let children = if depth < self.depth {
+                rng.gen_range(0..child_range)
+            } else {
+                0
+            };
+            let entries = rng.gen_range(1..=self.entries);
+            numbers.push(children);
+            numbers.push(entries);
+            stack.push((children, children, entries, depth));
 
-    let mut node = Node::new();
-    for _ in 0..total_children {
-        let child = build_tree(values)?;
-        node.children.push(child);
+            while let Some((remaining, children, entries, node_depth)) = stack.last_mut() {
+                if *remaining > 0 {
+                    *remaining -= 1;
+                    depth = *node_depth + 1;
+                    break;
+                }
+
+                for _ in 0..*entries {
+                    numbers.push(rng.gen_range(1..=*children + 1));
+                }
+                stack.pop();
+            }
+
+            if stack.is_empty() {
+                return build_tree(numbers.into_iter().map(Ok), true);
+            }
+        }
     }
+}
 
-    let mut entries = values.drain(0..total_entries).collect::<Vec<u32>>();
-    if entries.len() != total_entries {
-        bail!(
-            "expected {} metadata entries but got {}",
-            total_entries,
-            entries.len()
+impl Drop for Node {
+    // Dropping the children recursively would overflow the stack on deep trees, so
+    // flatten them out first.
+    fn drop(&mut self) {
+        let mut stack = mem::take(&mut self.children);
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
+}
+
+/// Streams whitespace separated numbers from a reader.
+struct Numbers<R: BufRead> {
+    bytes: io::Bytes<R>,
+    /// The number of bytes read so far.
+    offset: usize,
+}
+
+impl<R: BufRead> Numbers<R> {
+    fn new(reader: R) -> Numbers<R> {
+        Numbers {
+            bytes: reader.bytes(),
+            offset: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Numbers<R> {
+    type Item = Result<u32>;
+
+    fn next(&mut self) -> Option<Result<u32>> {
+        let mut number: Option<u32> = None;
+        for byte in &mut self.bytes {
+            let byte = match byte {
+                Ok(byte) => byte,
+                Err(e) => return Some(Err(e.into())),
+            };
+            let position = self.offset;
+            self.offset += 1;
+
+            match byte {
+                b'0'..=b'9' => {
+                    let digit = u32::from(byte - b'0');
+                    let value = number
+                        .unwrap_or(0)
+                        .checked_mul(10)
+                        .and_then(|n| n.checked_add(digit));
+                    match value {
+                        Some(value) => number = Some(value),
+                        None => return Some(Err(anyhow!("number too large at byte {}", position))),
+                    }
+                }
+                _ if byte.is_ascii_whitespace() => {
+                    if number.is_some() {
+                        break;
+                    }
+                }
+                _ => {
+                    return Some(Err(anyhow!(
+                        "unexpected character {:?} at byte {}",
+                        byte as char,
+                        position
+                    )))
+                }
+            }
+        }
+
+        number.map(Ok)
+    }
+}
+
+/// A node whose header has been read but whose children are still being built.
+struct Frame {
+    node: Node,
+    remaining_children: u32,
+    total_entries: u32,
+}
+
+/// Describes the node at the top of `stack` by its path from the root.
+fn describe_path(stack: &[Frame]) -> String {
+    if stack.len() <= 1 {
+        return "the root node".to_string();
+    }
+
+    // A node's index is the number of children its parent had finished before it.
+    let path: Vec<String> = stack
+        .windows(2)
+        .map(|frames| frames[0].node.children.len().to_string())
+        .collect();
+    format!("node {}", path.join("/"))
+}
+
+/// Builds a tree from the flat header/metadata format. Any numbers left over after the
+/// root node are an error if `strict`, and are otherwise ignored.
+fn build_tree(mut values: impl Iterator<Item = Result<u32>>, strict: bool) -> Result<Node> {
+    // The index of the next token to be read.
+    let mut offset = 0;
+    let mut next = |stack: &[Frame], what: &dyn Fn() -> String| -> Result<u32> {
+        let value = match values.next() {
+            Some(Ok(value)) => value,
+            Some(Err(e)) => {
+                return Err(e.context(format!(
+                    "invalid token {} reading {} of {}",
+                    offset,
+                    what(),
+                    describe_path(stack)
+                )))
+            }
+            None => bail!(
+                "unexpected end of input at token {} reading {} of {}",
+                offset,
+                what(),
+                describe_path(stack)
+            ),
+        };
+        offset += 1;
+        Ok(value)
+    };
+
+    let mut stack: Vec<Frame> = vec![];
+    loop {
+        stack.push(Frame {
+            node: Node::new(),
+            remaining_children: 0,
+            total_entries: 0,
+        });
+        let total_children = next(&stack, &|| "the number of child nodes".to_string())?;
+        let total_entries = next(&stack, &|| "the number of metadata entries".to_string())?;
+        let frame = stack.last_mut().unwrap();
+        frame.remaining_children = total_children;
+        frame.total_entries = total_entries;
+
+        // Finish off every node that has all of its children, handing each to its parent,
+        // until one still needs another child.
+        while let Some(frame) = stack.last() {
+            if frame.remaining_children > 0 {
+                break;
+            }
+
+            let total_entries = frame.total_entries;
+            let mut entries = Vec::with_capacity(total_entries as usize);
+            for i in 0..total_entries {
+                entries.push(next(&stack, &|| {
+                    format!("metadata entry {} of {}", i + 1, total_entries)
+                })?);
+            }
+
+            let mut frame = stack.pop().unwrap();
+            frame.node.entries = entries;
+            match stack.last_mut() {
+                Some(parent) => {
+                    parent.node.children.push(frame.node);
+                    parent.remaining_children -= 1;
+                }
+                None => {
+                    if strict {
+                        let trailing = values.count();
+                        if trailing > 0 {
+                            bail!(
+                                "{} trailing number{} after the root node, from token {}",
+                                trailing,
+                                if trailing == 1 { "" } else { "s" },
+                                offset
+                            );
+                        }
+                    }
+                    return Ok(frame.node);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Node {
+        build_tree(Numbers::new(input.as_bytes()), true).unwrap()
+    }
+
+    fn parse_error(input: &str, strict: bool) -> String {
+        match build_tree(Numbers::new(input.as_bytes()), strict) {
+            Ok(_) => panic!("expected {:?} to fail to parse", input),
+            Err(e) => format!("{:#}", e),
+        }
+    }
+
+    #[test]
+    fn example() {
+        let root = parse("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2\n");
+        assert_eq!(root.value1(), 138);
+        assert_eq!(root.value2(), 66);
+    }
+
+    #[test]
+    fn deep_chain() {
+        const DEPTH: usize = 1_000_000;
+
+        // Every node has a single child and a single metadata entry pointing at it.
+        let mut input = "1 1 ".repeat(DEPTH - 1);
+        input.push_str("0 1");
+        input.push_str(&" 1".repeat(DEPTH));
+
+        let root = parse(&input);
+        assert_eq!(root.value1(), DEPTH as u32);
+        assert_eq!(root.value2(), 1);
+    }
+
+    #[test]
+    fn pretty_prints_example() {
+        let root = parse("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2");
+        assert_eq!(
+            root.pretty("root"),
+            "\
+root: 2 children, entries [1 1 2], value1 138, value2 66
+  0: 0 children, entries [10 11 12], value1 33, value2 33
+  1: 1 child, entries [2], value1 101, value2 0
+    0: 0 children, entries [99], value1 99, value2 99
+"
         );
     }
 
-    node.entries.append(&mut entries);
+    #[test]
+    fn queries_by_path() {
+        let root = parse("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2");
+        let node = |path| {
+            root.at(&parse_path(path).unwrap())
+                .map(|n| n.entries.clone())
+        };
+        assert_eq!(node(""), Some(vec![1, 1, 2]));
+        assert_eq!(node("1"), Some(vec![2]));
+        assert_eq!(node("1/0"), Some(vec![99]));
+        assert_eq!(node("1/1"), None);
+        assert!(parse_path("1/x").is_err());
+    }
+
+    #[test]
+    fn round_trips() {
+        for seed in 0..200 {
+            let spec = Spec {
+                depth: (seed % 7) as usize,
+                children: 1 + (seed % 4) as u32,
+                entries: 1 + (seed % 5) as u32,
+                seed,
+            };
+            let tree = spec.generate().unwrap();
+            let flat = tree.serialise();
+            let reparsed = parse(&flat);
+
+            assert_eq!(reparsed.serialise(), flat, "{:?}", spec);
+            assert_eq!(reparsed.pretty("root"), tree.pretty("root"), "{:?}", spec);
+            assert_eq!(reparsed.values()[0], (tree.value1(), tree.value2()));
+        }
+    }
 
-    Ok(node)
+    #[test]
+    fn rejects_oversized_specs() {
+        assert!("3,4294967294,2".parse::<Spec>().is_ok());
+        assert_eq!(
+            format!("{:#}", "3,4294967295,2".parse::<Spec>().unwrap_err()),
+            "too many children for a tree spec: 4294967295"
+        );
+        assert!("3,4294967296,2".parse::<Spec>().is_err());
+    }
+
+    #[test]
+    fn accepts_any_whitespace() {
+        let root = parse("  2 3\t0  3\n10 11 12\r\n1 1 0 1 99 2\n\n1 1 2\n");
+        assert_eq!(root.serialise(), "2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2");
+    }
+
+    #[test]
+    fn reports_truncated_input() {
+        assert_eq!(
+            parse_error("", false),
+            "unexpected end of input at token 0 reading the number of child nodes of the \
+             root node"
+        );
+        assert_eq!(
+            parse_error("2 3 0 3 10 11 12 1", false),
+            "unexpected end of input at token 8 reading the number of metadata entries of \
+             node 1"
+        );
+        assert_eq!(
+            parse_error("2 3 0 3 10 11 12 1 1 0 3 99", false),
+            "unexpected end of input at token 12 reading metadata entry 2 of 3 of node 1/0"
+        );
+    }
+
+    #[test]
+    fn reports_invalid_tokens() {
+        assert_eq!(
+            parse_error("1 1 0 x", false),
+            "invalid token 3 reading the number of metadata entries of node 0: unexpected \
+             character 'x' at byte 6"
+        );
+    }
+
+    #[test]
+    fn rejects_trailing_data_when_strict() {
+        let input = "0 1 5 6 7";
+        assert_eq!(
+            build_tree(Numbers::new(input.as_bytes()), false)
+                .unwrap()
+                .entries,
+            vec![5]
+        );
+        assert_eq!(
+            parse_error(input, true),
+            "2 trailing numbers after the root node, from token 3"
+        );
+    }
 }
@@ -2,33 +2,383 @@ use anyhow::{anyhow, bail, Context, Result};
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::{
-    collections::HashMap,
-    io::{self, Read},
+    collections::{HashMap, VecDeque},
+    env, fs,
+    io::{self, Read, Write},
+    mem,
     str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Instant,
 };
 
 fn main() -> Result<()> {
-    let mut input = String::new();
-    io::stdin().read_to_string(&mut input)?;
+    let options = Options::from_args(env::args().skip(1))?;
 
-    let mut games = input
+    let mut setups = vec![];
+    if options.paths.is_empty() {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        setups.extend(read_setups(&input).context("Invalid games on stdin")?);
+    }
+    for path in &options.paths {
+        let input = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+        setups.extend(read_setups(&input).with_context(|| format!("Invalid games in {}", path))?);
+    }
+
+    match options.mode {
+        Mode::Summary => summarise(&setups, &options),
+        Mode::Bench => bench(&setups, &options),
+        Mode::Csv => export_csv(&setups, &options),
+        Mode::Dump(turns) => {
+            for (num, setup) in setups.iter().enumerate() {
+                println!("Game {}:", num + 1);
+                print!("{}", options.circle.dump(setup, options.rules, turns)?);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Reads one game per line, skipping blank lines and `#` comments.
+fn read_setups(input: &str) -> Result<Vec<Setup>> {
+    input
         .lines()
-        .map(|v| v.parse())
-        .collect::<Result<Vec<Game>>>()?;
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(num, line)| {
+            line.parse()
+                .with_context(|| format!("Invalid game on line {}", num + 1))
+        })
+        .collect()
+}
 
-    for (num, game) in games.iter_mut().enumerate() {
-        let (winner_id, score) = game.run()?;
+/// Plays every game with every multiplier across all available threads, returning the
+/// outcomes in order as `(game index, multiplier, outcome)`.
+fn play_all(setups: &[Setup], options: &Options) -> Result<Vec<(usize, usize, Outcome)>> {
+    let jobs: Vec<(usize, usize)> = (0..setups.len())
+        .flat_map(|game| options.multipliers.iter().map(move |m| (game, *m)))
+        .collect();
+    let threads = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(jobs.len());
+
+    // Each thread takes the next job that hasn't been started until there are none left.
+    let next_job = AtomicUsize::new(0);
+    let record_history = matches!(options.mode, Mode::Csv);
+    let mut outcomes: Vec<(usize, Result<Outcome>)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut outcomes = vec![];
+                    loop {
+                        let job = next_job.fetch_add(1, Ordering::Relaxed);
+                        let (game, multiplier) = match jobs.get(job) {
+                            Some(job) => *job,
+                            None => break,
+                        };
+                        let outcome = options.circle.play(
+                            &setups[game],
+                            multiplier,
+                            options.rules,
+                            record_history,
+                        );
+                        outcomes.push((job, outcome));
+                    }
+                    outcomes
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Game thread panicked"))
+            .collect()
+    });
+    outcomes.sort_by_key(|(job, _)| *job);
+
+    outcomes
+        .into_iter()
+        .map(|(job, outcome)| {
+            let (game, multiplier) = jobs[job];
+            let outcome = outcome.with_context(|| format!("Failed to play game {}", game + 1))?;
+            Ok((game, multiplier, outcome))
+        })
+        .collect()
+}
+
+/// Reports the winner of every game, checking the high score of those that give one to
+/// expect.
+fn summarise(setups: &[Setup], options: &Options) -> Result<()> {
+    let mut mismatches = 0;
+    let outcomes = play_all(setups, options)?;
+    for (game, multiplier, outcome) in &outcomes {
+        let (winner_id, score) = outcome.winner;
+        let check = match setups[*game].expected_score(*multiplier, options.rules) {
+            Some(expected) if expected == score => " (as expected)".to_string(),
+            Some(expected) => {
+                mismatches += 1;
+                format!(" (MISMATCH: expected {})", expected)
+            }
+            None => String::new(),
+        };
         println!(
-            "Game {}: Player {} won with high score is {}",
-            num + 1,
+            "Game {} (last marble x{}): Player {} won with high score is {}{}",
+            game + 1,
+            multiplier,
             winner_id,
-            score
+            score,
+            check
+        );
+    }
+
+    if mismatches > 0 {
+        bail!(
+            "{} of {} games did not score as expected",
+            mismatches,
+            outcomes.len()
         );
     }
 
     Ok(())
 }
 
+/// Writes every scoring turn of every game as CSV. Only the scoring player's total changes
+/// on each turn, so every player's score at any point is the total of their latest row.
+fn export_csv(setups: &[Setup], options: &Options) -> Result<()> {
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    writeln!(
+        out,
+        "game,multiplier,player,marble,removed,points,total,leader,lead_change"
+    )?;
+    for (game, multiplier, outcome) in play_all(setups, options)? {
+        for turn in &outcome.history {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{}",
+                game + 1,
+                multiplier,
+                turn.player,
+                turn.marble,
+                turn.removed,
+                turn.points,
+                turn.total,
+                turn.leader,
+                turn.lead_changed
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Plays every game with each kind of circle, comparing their speed and memory use.
+fn bench(setups: &[Setup], options: &Options) -> Result<()> {
+    for (num, setup) in setups.iter().enumerate() {
+        for multiplier in &options.multipliers {
+            let mut winners = vec![];
+            for kind in CircleKind::ALL {
+                let started = Instant::now();
+                let outcome = kind.play(setup, *multiplier, options.rules, false)?;
+                println!(
+                    "Game {} (last marble x{}) with {:?} circle: {:.2?}, {} KiB",
+                    num + 1,
+                    multiplier,
+                    kind,
+                    started.elapsed(),
+                    outcome.heap_size / 1024
+                );
+                winners.push(outcome.winner);
+            }
+
+            if winners.windows(2).any(|pair| pair[0] != pair[1]) {
+                bail!(
+                    "Circles disagree on the winner of game {}: {:?}",
+                    num + 1,
+                    winners
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+struct Options {
+    /// The multipliers to play each game with, scaling the value of its last marble.
+    multipliers: Vec<usize>,
+    rules: Rules,
+    circle: CircleKind,
+    mode: Mode,
+    /// Files of games to play, one per line. Games are read from stdin if there are none.
+    paths: Vec<String>,
+}
+
+/// Selects what the program reports about each game.
+enum Mode {
+    /// Print the winner and their score.
+    Summary,
+    /// Time every kind of circle playing the game.
+    Bench,
+    /// Print every scoring turn as CSV.
+    Csv,
+    /// Print the circle after each of the first few turns, as in the puzzle's example.
+    Dump(usize),
+}
+
+impl Options {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Options> {
+        let mut multipliers = vec![];
+        let mut rules = Rules::default();
+        let mut circle = CircleKind::Linked;
+        let mut mode = Mode::Summary;
+        let mut paths = vec![];
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("Missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--multiplier" => multipliers.push(value()?.parse()?),
+                "--circle" => circle = value()?.parse()?,
+                "--bench" => mode = Mode::Bench,
+                "--csv" => mode = Mode::Csv,
+                "--dump" => mode = Mode::Dump(value()?.parse()?),
+                "--rules" => {
+                    let path = value()?;
+                    let config = fs::read_to_string(&path)
+                        .with_context(|| format!("Failed to read rules from {}", path))?;
+                    rules
+                        .configure(&config)
+                        .with_context(|| format!("Invalid rules in {}", path))?;
+                }
+                "--modulus" | "--removal" | "--insertion" | "--start" => {
+                    rules.set(&arg[2..], &value()?)?
+                }
+                _ if !arg.starts_with('-') => paths.push(arg),
+                _ => bail!(
+                    "Usage: day09 [--multiplier N]... [--rules FILE] [--modulus N] \
+                     [--removal N] [--insertion N] [--start N] [--circle linked|deque] \
+                     [--bench | --csv | --dump TURNS] [FILE]... (got {})",
+                    arg
+                ),
+            }
+        }
+
+        // Games are played as given unless asked otherwise, since the puzzle's part two
+        // game can be given directly as input.
+        if multipliers.is_empty() {
+            multipliers.push(1);
+        }
+
+        Ok(Options {
+            multipliers,
+            rules,
+            circle,
+            mode,
+            paths,
+        })
+    }
+}
+
+/// The rules governing how marbles are placed and scored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Rules {
+    /// Marbles whose value is a multiple of this are kept and scored rather than placed.
+    modulus: MarbleValue,
+    /// How many marbles counter-clockwise of the current one the removed marble is.
+    removal_offset: usize,
+    /// How many marbles clockwise of the current one new marbles are placed after.
+    insertion_offset: usize,
+    /// The value of the marble the circle starts with. Play continues from the next value.
+    starting_marble: MarbleValue,
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules {
+            modulus: 23,
+            removal_offset: 7,
+            insertion_offset: 1,
+            starting_marble: 0,
+        }
+    }
+}
+
+impl Rules {
+    /// Applies a config file of `key = value` lines, where `#` starts a comment and the
+    /// keys are those accepted by [`Rules::set`].
+    fn configure(&mut self, config: &str) -> Result<()> {
+        for (num, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected key = value on line {}: {}", num + 1, line))?;
+            self.set(key.trim(), value.trim())
+                .with_context(|| format!("Invalid rule on line {}", num + 1))?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the rule named `key`: one of `modulus`, `removal`, `insertion` or `start`.
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let invalid = || format!("Invalid value for {}: {}", key, value);
+        match key {
+            "modulus" => {
+                let modulus = value.parse().with_context(invalid)?;
+                if modulus < 2 {
+                    bail!("Scoring modulus must be at least 2 (got {})", modulus);
+                }
+                self.modulus = modulus;
+            }
+            "removal" => self.removal_offset = value.parse().with_context(invalid)?,
+            "insertion" => self.insertion_offset = value.parse().with_context(invalid)?,
+            "start" => self.starting_marble = value.parse().with_context(invalid)?,
+            _ => bail!("Unknown rule: {}", key),
+        }
+
+        Ok(())
+    }
+}
+
+type MarbleValue = u32;
+
+type Player = u32;
+
+type Points = u64;
+
+type Score = HashMap<Player, Points>;
+
+/// A circle of marbles, one of which is the current marble.
+trait Circle {
+    /// Creates a circle holding just `marble`, which is current.
+    fn new(marble: MarbleValue) -> Self;
+
+    /// The number of marbles in the circle.
+    fn len(&self) -> usize;
+
+    /// Places `marble` after the marble `offset` places clockwise of the current one, and
+    /// makes it current.
+    fn insert(&mut self, offset: usize, marble: MarbleValue);
+
+    /// Takes the marble `offset` places counter-clockwise of the current one out of the
+    /// circle, and makes the marble that was clockwise of it current.
+    fn remove(&mut self, offset: usize) -> MarbleValue;
+
+    /// The marbles in clockwise order, starting from the current one.
+    fn clockwise(&self) -> Vec<MarbleValue>;
+
+    /// The number of bytes allocated to hold the circle.
+    fn heap_size(&self) -> usize;
+}
+
 #[derive(Clone, Debug)]
 struct Marble {
     value: MarbleValue,
@@ -36,46 +386,282 @@ struct Marble {
     prev_index: usize,
 }
 
-type MarbleValue = u32;
+/// A doubly linked list of marbles. Removed marbles are unlinked but never freed.
+struct LinkedCircle {
+    marbles: Vec<Marble>,
+    current_marble_index: usize,
+    len: usize,
+}
 
-type Player = u32;
+impl LinkedCircle {
+    fn index_of_next(&self, n: usize) -> usize {
+        let mut next_index = self.current_marble_index;
+        for _ in 0..n {
+            next_index = self.marbles[next_index].next_index;
+        }
+        next_index
+    }
+
+    fn index_of_prev(&self, n: usize) -> usize {
+        let mut prev_index = self.current_marble_index;
+        for _ in 0..n {
+            prev_index = self.marbles[prev_index].prev_index;
+        }
+        prev_index
+    }
+}
+
+impl Circle for LinkedCircle {
+    fn new(marble: MarbleValue) -> LinkedCircle {
+        LinkedCircle {
+            marbles: vec![Marble {
+                value: marble,
+                next_index: 0,
+                prev_index: 0,
+            }],
+            current_marble_index: 0,
+            len: 1,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn insert(&mut self, offset: usize, marble: MarbleValue) {
+        let prev_index = self.index_of_next(offset);
+        let next_index = self.marbles[prev_index].next_index;
+        let next_marble = Marble {
+            value: marble,
+            next_index,
+            prev_index,
+        };
+        let next_marble_index = self.marbles.len();
+
+        self.marbles[next_index].prev_index = next_marble_index;
+        self.marbles[prev_index].next_index = next_marble_index;
+
+        self.marbles.push(next_marble);
+        self.current_marble_index = next_marble_index;
+        self.len += 1;
+    }
+
+    fn remove(&mut self, offset: usize) -> MarbleValue {
+        let removed = self.marbles[self.index_of_prev(offset)].clone();
+        self.marbles[removed.prev_index].next_index = removed.next_index;
+        self.marbles[removed.next_index].prev_index = removed.prev_index;
+        self.current_marble_index = removed.next_index;
+        self.len -= 1;
+
+        removed.value
+    }
+
+    fn clockwise(&self) -> Vec<MarbleValue> {
+        let mut marbles = Vec::with_capacity(self.len);
+        let mut index = self.current_marble_index;
+        for _ in 0..self.len {
+            marbles.push(self.marbles[index].value);
+            index = self.marbles[index].next_index;
+        }
+        marbles
+    }
+
+    fn heap_size(&self) -> usize {
+        self.marbles.capacity() * mem::size_of::<Marble>()
+    }
+}
+
+/// A ring buffer of marble values that is rotated to keep the current marble at the back,
+/// so that the marble clockwise of it is at the front.
+struct DequeCircle {
+    marbles: VecDeque<MarbleValue>,
+}
+
+impl Circle for DequeCircle {
+    fn new(marble: MarbleValue) -> DequeCircle {
+        DequeCircle {
+            marbles: VecDeque::from(vec![marble]),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.marbles.len()
+    }
+
+    fn insert(&mut self, offset: usize, marble: MarbleValue) {
+        let len = self.marbles.len();
+        self.marbles.rotate_left(offset % len);
+        self.marbles.push_back(marble);
+    }
+
+    fn remove(&mut self, offset: usize) -> MarbleValue {
+        let len = self.marbles.len();
+        self.marbles.rotate_right(offset % len);
+        let removed = self.marbles.pop_back().unwrap();
+        if !self.marbles.is_empty() {
+            self.marbles.rotate_left(1);
+        }
+
+        removed
+    }
+
+    fn clockwise(&self) -> Vec<MarbleValue> {
+        let mut marbles: Vec<MarbleValue> = self.marbles.iter().copied().collect();
+        marbles.rotate_right(1);
+        marbles
+    }
+
+    fn heap_size(&self) -> usize {
+        self.marbles.capacity() * mem::size_of::<MarbleValue>()
+    }
+}
+
+/// Selects which implementation of [`Circle`] games are played with.
+#[derive(Clone, Copy, Debug)]
+enum CircleKind {
+    Linked,
+    Deque,
+}
+
+impl CircleKind {
+    const ALL: [CircleKind; 2] = [CircleKind::Linked, CircleKind::Deque];
+
+    /// Plays a game to the end with this kind of circle, keeping every scoring turn if
+    /// `record_history` is set.
+    fn play(
+        self,
+        setup: &Setup,
+        multiplier: usize,
+        rules: Rules,
+        record_history: bool,
+    ) -> Result<Outcome> {
+        fn play_with<C: Circle>(
+            setup: &Setup,
+            multiplier: usize,
+            rules: Rules,
+            record_history: bool,
+        ) -> Result<Outcome> {
+            let mut game = setup.game::<C>(multiplier, rules)?;
+            if record_history {
+                game.record_history();
+            }
+            let winner = game.run()?;
+            Ok(Outcome {
+                winner,
+                heap_size: game.circle.heap_size(),
+                history: game.history.unwrap_or_default(),
+            })
+        }
+
+        match self {
+            CircleKind::Linked => {
+                play_with::<LinkedCircle>(setup, multiplier, rules, record_history)
+            }
+            CircleKind::Deque => play_with::<DequeCircle>(setup, multiplier, rules, record_history),
+        }
+    }
+
+    /// Renders the circle after each of the first `turns` turns of a game.
+    fn dump(self, setup: &Setup, rules: Rules, turns: usize) -> Result<String> {
+        fn dump_with<C: Circle>(setup: &Setup, rules: Rules, turns: usize) -> Result<String> {
+            let mut game = setup.game::<C>(1, rules)?;
+            game.validate()?;
+
+            let marble_width = (rules.starting_marble as usize + turns).to_string().len();
+            let mut output = game.render(None, marble_width);
+            for _ in 0..turns {
+                let player = game.next_player;
+                if !game.play_next() {
+                    break;
+                }
+                output.push_str(&game.render(Some(player), marble_width));
+            }
 
-type Score = HashMap<Player, u32>;
+            Ok(output)
+        }
 
-struct Game {
+        match self {
+            CircleKind::Linked => dump_with::<LinkedCircle>(setup, rules, turns),
+            CircleKind::Deque => dump_with::<DequeCircle>(setup, rules, turns),
+        }
+    }
+}
+
+impl FromStr for CircleKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linked" => Ok(CircleKind::Linked),
+            "deque" => Ok(CircleKind::Deque),
+            _ => bail!("Unknown circle: {} (expected linked or deque)", s),
+        }
+    }
+}
+
+/// The result of playing a game to the end.
+struct Outcome {
+    winner: (Player, Points),
+    /// Every turn on which a player scored, in order, if the history was recorded.
+    history: Vec<ScoringTurn>,
+    /// The number of bytes allocated to the circle by the end of the game.
+    heap_size: usize,
+}
+
+/// A turn on which a player kept their marble and scored.
+#[derive(Clone, Debug)]
+struct ScoringTurn {
+    player: Player,
+    marble: MarbleValue,
+    /// The marble taken from the circle.
+    removed: MarbleValue,
+    points: Points,
+    /// The player's total score after this turn. Other players' scores are unchanged
+    /// since their own latest scoring turns.
+    total: Points,
+    /// The player with the highest score after this turn.
+    leader: Player,
+    /// Whether this turn put a new player in the lead.
+    lead_changed: bool,
+}
+
+struct Game<C> {
+    rules: Rules,
     total_players: usize,
     total_marbles: usize,
-    circle: Vec<Marble>,
-    current_marble_index: usize,
+    circle: C,
     next_marble_value: MarbleValue,
     next_player: Player,
     score: Score,
+    /// The player with the highest score, once anyone has scored.
+    leader: Option<Player>,
+    /// Every scoring turn so far, if asked to record them.
+    history: Option<Vec<ScoringTurn>>,
 }
 
-impl Game {
-    fn new(total_players: usize, total_marbles: usize) -> Game {
+impl<C: Circle> Game<C> {
+    fn new(total_players: usize, total_marbles: usize, rules: Rules) -> Game<C> {
         Game {
+            rules,
             total_players,
             total_marbles,
-            circle: vec![Marble {
-                value: 0,
-                next_index: 0,
-                prev_index: 0,
-            }],
-            current_marble_index: 0,
-            next_marble_value: 1,
+            circle: C::new(rules.starting_marble),
+            // A start beyond any possible game is caught by `validate` rather than wrapping.
+            next_marble_value: rules.starting_marble.saturating_add(1),
             next_player: 1,
             score: Score::new(),
+            leader: None,
+            history: None,
         }
     }
 
-    fn run(&mut self) -> Result<(Player, u32)> {
-        if self.total_marbles == 0 {
-            bail!("Game can't be played without marbles");
-        }
-        if self.total_players == 0 {
-            bail!("Game can't be played without players");
-        }
+    /// Keeps a record of every scoring turn from now on.
+    fn record_history(&mut self) {
+        self.history.get_or_insert_with(Vec::new);
+    }
+
+    fn run(&mut self) -> Result<(Player, Points)> {
+        self.validate()?;
 
         while self.play_next() {}
 
@@ -86,32 +672,71 @@ impl Game {
             .context("Game did not produce a winner")
     }
 
+    fn validate(&self) -> Result<()> {
+        if self.total_marbles == 0 {
+            bail!("Game can't be played without marbles");
+        }
+        if self.total_players == 0 {
+            bail!("Game can't be played without players");
+        }
+        if MarbleValue::try_from(self.total_marbles).is_err() {
+            bail!("Game can't be played with {} marbles", self.total_marbles);
+        }
+        if self.rules.starting_marble as usize >= self.total_marbles {
+            bail!(
+                "Game can't start with marble {} when the last marble is {}",
+                self.rules.starting_marble,
+                self.total_marbles - 1
+            );
+        }
+
+        Ok(())
+    }
+
     fn play_next(&mut self) -> bool {
-        if self.next_marble_value == self.total_marbles as u32 {
+        if self.next_marble_value as usize >= self.total_marbles {
             return false;
         }
 
-        if self.next_marble_value % 23 == 0 {
-            let prev_7 = self.prev(7).clone();
-            self.prev(8).next_index = prev_7.next_index;
-            self.prev(6).prev_index = prev_7.prev_index;
-            self.current_marble_index = prev_7.next_index;
+        // A scoring marble can't take the only marble from the circle, so it's placed as
+        // normal if it comes first.
+        if self.next_marble_value.is_multiple_of(self.rules.modulus) && self.circle.len() > 1 {
+            let removed = self.circle.remove(self.rules.removal_offset);
 
-            let points = (self.next_marble_value + prev_7.value) as u32;
-            *self.score.entry(self.next_player).or_default() += points;
-        } else {
-            let next_marble = Marble {
-                value: self.next_marble_value,
-                next_index: self.next(1).next_index,
-                prev_index: self.curr().next_index,
-            };
-            let next_marble_index = self.circle.len();
+            let points = Points::from(self.next_marble_value) + Points::from(removed);
+            let score = self.score.entry(self.next_player).or_default();
+            debug_assert!(
+                score.checked_add(points).is_some(),
+                "Player {} score overflowed",
+                self.next_player
+            );
+            *score += points;
+            let total = *score;
 
-            self.next(2).prev_index = next_marble_index;
-            self.next(1).next_index = next_marble_index;
+            // The lead only changes hands when someone overtakes the leader outright.
+            let previous_leader = self.leader;
+            let leader = match previous_leader {
+                Some(leader) if leader != self.next_player && self.score[&leader] >= total => {
+                    leader
+                }
+                _ => self.next_player,
+            };
+            self.leader = Some(leader);
 
-            self.circle.push(next_marble);
-            self.current_marble_index = next_marble_index;
+            if let Some(history) = &mut self.history {
+                history.push(ScoringTurn {
+                    player: self.next_player,
+                    marble: self.next_marble_value,
+                    removed,
+                    points,
+                    total,
+                    leader,
+                    lead_changed: previous_leader != Some(leader),
+                });
+            }
+        } else {
+            self.circle
+                .insert(self.rules.insertion_offset, self.next_marble_value);
         }
 
         self.next_marble_value += 1;
@@ -120,34 +745,84 @@ impl Game {
         true
     }
 
-    fn curr(&mut self) -> &mut Marble {
-        self.circle.get_mut(self.current_marble_index).unwrap()
-    }
+    /// Renders the circle in the style of the puzzle's example, as a line such as
+    /// `[1]  0 (1)` starting from the lowest marble, with the current marble in brackets
+    /// and the player who last played (if any) at the start.
+    fn render(&self, player: Option<Player>, marble_width: usize) -> String {
+        let player_width = self.total_players.to_string().len();
+        let mut line = match player {
+            Some(player) => format!("[{:>1$}]", player, player_width),
+            None => format!("[{:>1$}]", "-", player_width),
+        };
 
-    fn next(&mut self, n: usize) -> &mut Marble {
-        let mut next_index = self.current_marble_index;
-        for _ in 0..n {
-            next_index = self.circle[next_index].next_index;
+        // Each marble is right-aligned in a cell with a space to spare, which the brackets
+        // around the current marble take up.
+        let marbles = self.circle.clockwise();
+        let start = (0..marbles.len()).min_by_key(|i| marbles[*i]).unwrap_or(0);
+        let mut after_current = false;
+        for i in 0..marbles.len() {
+            let index = (start + i) % marbles.len();
+            let current = index == 0;
+            let cell = if current {
+                format!("{:>1$}", format!("({}", marbles[index]), marble_width + 1)
+            } else {
+                format!("{:>1$}", marbles[index], marble_width + 1)
+            };
+
+            if after_current {
+                line.push(')');
+                line.push_str(&cell[1..]);
+            } else {
+                line.push_str(&cell);
+            }
+            after_current = current;
+        }
+        if after_current {
+            line.push(')');
         }
-        self.circle.get_mut(next_index).unwrap()
+
+        line.push('\n');
+        line
     }
+}
 
-    fn prev(&mut self, n: usize) -> &mut Marble {
-        let mut prev_index = self.current_marble_index;
-        for _ in 0..n {
-            prev_index = self.circle[prev_index].prev_index;
-        }
-        self.circle.get_mut(prev_index).unwrap()
+/// The players and last marble of a game, as described by a line of input, along with
+/// the high score it should produce if given.
+#[derive(Debug)]
+struct Setup {
+    total_players: usize,
+    last_marble: usize,
+    expected: Option<Points>,
+}
+
+impl Setup {
+    /// Creates a game played by `rules` with the value of the last marble scaled by
+    /// `multiplier`.
+    fn game<C: Circle>(&self, multiplier: usize, rules: Rules) -> Result<Game<C>> {
+        let last_marble = self
+            .last_marble
+            .checked_mul(multiplier)
+            .ok_or_else(|| anyhow!("Last marble overflowed with multiplier {}", multiplier))?;
+
+        Ok(Game::new(self.total_players, last_marble + 1, rules))
+    }
+
+    /// The high score the game should produce when played with `multiplier` and `rules`,
+    /// if known. Expected scores only apply when the game is played as described, so are
+    /// ignored with other rules or multipliers.
+    fn expected_score(&self, multiplier: usize, rules: Rules) -> Option<Points> {
+        self.expected
+            .filter(|_| multiplier == 1 && rules == Rules::default())
     }
 }
 
-impl FromStr for Game {
+impl FromStr for Setup {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         lazy_static! {
             static ref RE: Regex = Regex::new(
-                r"^(?P<players>\d+) players; last marble is worth (?P<points>\d+) points$"
+                r"^(?P<players>\d+) players; last marble is worth (?P<points>\d+) points(?:: high score is (?P<expected>\d+))?$"
             )
             .unwrap();
         }
@@ -156,6 +831,232 @@ impl FromStr for Game {
             .captures(s)
             .ok_or_else(|| anyhow!("Invalid line: {}", s))?;
 
-        Ok(Game::new(caps["players"].parse()?, caps["points"].parse::<usize>()? + 1))
+        Ok(Setup {
+            total_players: caps["players"].parse()?,
+            last_marble: caps["points"].parse()?,
+            expected: caps
+                .name("expected")
+                .map(|m| m.as_str().parse())
+                .transpose()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play<C: Circle>(setup: &str, rules: Rules) -> Result<Game<C>> {
+        let mut game = setup.parse::<Setup>()?.game::<C>(1, rules)?;
+        game.run()?;
+        Ok(game)
+    }
+
+    #[test]
+    fn default_rules() {
+        for (setup, high_score) in [
+            ("9 players; last marble is worth 25 points", 32),
+            ("10 players; last marble is worth 1618 points", 8317),
+            ("13 players; last marble is worth 7999 points", 146373),
+        ] {
+            for kind in CircleKind::ALL {
+                let outcome = kind.play(&setup.parse().unwrap(), 1, Rules::default(), false);
+                assert_eq!(
+                    outcome.unwrap().winner.1,
+                    high_score,
+                    "{} ({:?})",
+                    setup,
+                    kind
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn circles_play_examples() {
+        fn check<C: Circle>(setup: &Setup) -> Game<C> {
+            let mut game = setup.game::<C>(1, Rules::default()).unwrap();
+            let (_, high_score) = game.run().unwrap();
+            assert_eq!(Some(high_score), setup.expected);
+            game
+        }
+
+        let setups = read_setups(include_str!("../input/examples.txt")).unwrap();
+        assert_eq!(setups.len(), 6);
+        for setup in &setups {
+            let linked = check::<LinkedCircle>(setup);
+            let deque = check::<DequeCircle>(setup);
+            assert_eq!(linked.score, deque.score);
+            assert_eq!(linked.circle.clockwise(), deque.circle.clockwise());
+        }
+
+        // The circle at the end of the first example, starting from marble 25.
+        let game = check::<DequeCircle>(&setups[0]);
+        assert_eq!(
+            game.circle.clockwise(),
+            vec![
+                25, 10, 21, 5, 22, 11, 1, 12, 6, 13, 3, 14, 7, 15, 0, 16, 8, 17, 4, 18, 19, 2, 24,
+                20
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_setups() {
+        let setups = read_setups(
+            "# Games\n\n  9 players; last marble is worth 25 points: high score is 32\n\
+             \t# An indented comment\n  \n10 players; last marble is worth 1618 points  \n",
+        )
+        .unwrap();
+        let setups: Vec<_> = setups
+            .iter()
+            .map(|s| (s.total_players, s.last_marble, s.expected))
+            .collect();
+        assert_eq!(setups, vec![(9, 25, Some(32)), (10, 1618, None)]);
+
+        let err = read_setups("# Games\n9 players; last marble is worth 25 points\n9 players\n")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Invalid game on line 3");
+    }
+
+    #[test]
+    fn plays_batches_in_order() {
+        let setups = read_setups(include_str!("../input/examples.txt")).unwrap();
+        let options = Options {
+            multipliers: vec![1, 2],
+            rules: Rules::default(),
+            circle: CircleKind::Deque,
+            mode: Mode::Summary,
+            paths: vec![],
+        };
+        let outcomes = play_all(&setups, &options).unwrap();
+
+        let jobs: Vec<_> = outcomes.iter().map(|(game, m, _)| (*game, *m)).collect();
+        let expected_jobs: Vec<_> = (0..setups.len())
+            .flat_map(|game| [(game, 1), (game, 2)])
+            .collect();
+        assert_eq!(jobs, expected_jobs);
+
+        for (game, multiplier, outcome) in &outcomes {
+            let single = CircleKind::Linked
+                .play(&setups[*game], *multiplier, Rules::default(), false)
+                .unwrap();
+            assert_eq!(
+                outcome.winner, single.winner,
+                "game {} x{}",
+                game, multiplier
+            );
+            assert!(outcome.history.is_empty());
+        }
+    }
+
+    #[test]
+    fn checks_expected_scores_as_described() {
+        let setup: Setup = "9 players; last marble is worth 25 points: high score is 32"
+            .parse()
+            .unwrap();
+        assert_eq!(setup.expected_score(1, Rules::default()), Some(32));
+        assert_eq!(setup.expected_score(2, Rules::default()), None);
+        let rules = Rules {
+            modulus: 5,
+            ..Rules::default()
+        };
+        assert_eq!(setup.expected_score(1, rules), None);
+
+        let setup: Setup = "9 players; last marble is worth 25 points".parse().unwrap();
+        assert_eq!(setup.expected_score(1, Rules::default()), None);
+    }
+
+    #[test]
+    fn configures_rules() {
+        let mut rules = Rules::default();
+        rules
+            .configure("modulus = 5\nremoval = 2  # from the current marble\n\ninsertion = 0\n")
+            .unwrap();
+        rules.set("start", "3").unwrap();
+        assert_eq!(
+            rules,
+            Rules {
+                modulus: 5,
+                removal_offset: 2,
+                insertion_offset: 0,
+                starting_marble: 3,
+            }
+        );
+
+        assert!(rules.set("modulus", "1").is_err());
+        assert!(rules.set("offset", "1").is_err());
+        assert!(rules.configure("modulus 5").is_err());
+    }
+
+    #[test]
+    fn custom_rules() {
+        fn check<C: Circle>() {
+            let rules = Rules {
+                modulus: 5,
+                removal_offset: 2,
+                insertion_offset: 0,
+                starting_marble: 3,
+            };
+            let game = play::<C>("2 players; last marble is worth 10 points", rules).unwrap();
+
+            // Marble 5 takes marble 4, and marble 10 takes marble 7 from 3 6 7 8 (9).
+            assert_eq!(game.score[&1], 17);
+            assert_eq!(game.score[&2], 9);
+            assert_eq!(game.circle.clockwise(), vec![8, 9, 3, 6]);
+        }
+
+        check::<LinkedCircle>();
+        check::<DequeCircle>();
+    }
+
+    #[test]
+    fn records_history_when_asked() {
+        let setup = "9 players; last marble is worth 25 points".parse().unwrap();
+        let outcome = CircleKind::Linked
+            .play(&setup, 1, Rules::default(), false)
+            .unwrap();
+        assert!(outcome.history.is_empty());
+
+        // Player 5 scores 23 + 9 on the only scoring turn, taking the lead.
+        let outcome = CircleKind::Linked
+            .play(&setup, 1, Rules::default(), true)
+            .unwrap();
+        let turns: Vec<_> = outcome
+            .history
+            .iter()
+            .map(|t| {
+                (
+                    t.player,
+                    t.marble,
+                    t.removed,
+                    t.total,
+                    t.leader,
+                    t.lead_changed,
+                )
+            })
+            .collect();
+        assert_eq!(turns, vec![(5, 23, 9, 32, 5, true)]);
+    }
+
+    #[test]
+    fn rejects_start_beyond_last_marble() {
+        let setup = "9 players; last marble is worth 25 points";
+        for start in [26, 30, MarbleValue::MAX] {
+            let rules = Rules {
+                starting_marble: start,
+                ..Rules::default()
+            };
+            assert!(
+                play::<LinkedCircle>(setup, rules).is_err(),
+                "start {}",
+                start
+            );
+            assert!(
+                play::<DequeCircle>(setup, rules).is_err(),
+                "start {}",
+                start
+            );
+        }
     }
 }
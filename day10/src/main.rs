@@ -1,18 +1,29 @@
-use anyhow::{anyhow, Result};
-use core::time;
+use anyhow::{anyhow, bail, Result};
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::{
     collections::HashSet,
+    env,
     fmt::Display,
     io::{self, Read},
     str::FromStr,
-    thread,
 };
 
-const MAX_PLOT_AREA: i32 = 3000;
+mod ocr;
+mod viewer;
+
+/// The most cells the points can cover when they come together. Points spread any wider
+/// than this at their closest aren't spelling out a message, and would take too long to
+/// render.
+const MAX_MESSAGE_AREA: i64 = 10_000;
 
 fn main() -> Result<()> {
+    let view = match env::args().nth(1).as_deref() {
+        None => false,
+        Some("--view") => true,
+        Some(arg) => bail!("Usage: day10 [--view] (got {})", arg),
+    };
+
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
 
@@ -21,28 +32,37 @@ fn main() -> Result<()> {
         .map(|v| v.parse())
         .collect::<Result<Vec<Point>>>()?;
 
-    let mut plot = Plot::new(points);
-    run_simulation(&mut plot);
-
-    Ok(())
-}
+    let plot = Plot::new(points);
+    let convergence = plot.converge()?;
+    if view {
+        return viewer::view(&plot, convergence.seconds);
+    }
 
-fn run_simulation(plot: &mut Plot) {
-    let mut seconds = 0;
-    while plot.area() > MAX_PLOT_AREA {
-        plot.advance();
-        seconds += 1;
+    let message = ocr::read(&convergence.plot.bitmap());
+    if !message.unknown.is_empty() {
+        eprintln!(
+            "Unrecognised letters in the message:\n{}",
+            convergence.frame
+        );
+        for glyph in &message.unknown {
+            eprintln!("Unknown glyph at column {}:", glyph.column);
+            for row in &glyph.rows {
+                eprintln!("{}", row);
+            }
+        }
     }
 
-    while plot.area() <= MAX_PLOT_AREA {
-        println!("Simulated time: {} seconds", seconds);
-        println!("{}", plot);
+    println!("Answer to part 1: {}", message.text);
+    println!("Answer to part 2: {} seconds", convergence.seconds);
 
-        plot.advance();
-        seconds += 1;
+    Ok(())
+}
 
-        thread::sleep(time::Duration::from_millis(300));
-    }
+/// The moment the points come closest together, which is when they spell out a message.
+struct Convergence {
+    seconds: i32,
+    plot: Plot,
+    frame: String,
 }
 
 #[derive(Clone, Debug)]
@@ -54,12 +74,36 @@ struct Point {
 }
 
 impl Point {
-    fn advance(&mut self) {
-        self.x += self.vx;
-        self.y += self.vy;
+    /// Returns where the point will be after `seconds`, or `None` if that's off the `i32`
+    /// plane.
+    fn advanced_by(&self, seconds: i32) -> Option<Point> {
+        // The product of two `i32`s always fits in an `i64`, as does adding another.
+        let position =
+            |p: i32, v: i32| i32::try_from(i64::from(p) + i64::from(v) * i64::from(seconds)).ok();
+        Some(Point {
+            x: position(self.x, self.vx)?,
+            y: position(self.y, self.vy)?,
+            vx: self.vx,
+            vy: self.vy,
+        })
+    }
+
+    /// The furthest the point can move forwards in time without leaving the `i32` plane.
+    fn max_seconds(&self) -> i32 {
+        let limit = |p: i32, v: i32| {
+            let room = if v > 0 {
+                i64::from(i32::MAX) - i64::from(p)
+            } else {
+                i64::from(p) - i64::from(i32::MIN)
+            };
+            room.checked_div(i64::from(v).abs()).unwrap_or(i64::MAX)
+        };
+        let seconds = limit(self.x, self.vx).min(limit(self.y, self.vy));
+        i32::try_from(seconds).unwrap_or(i32::MAX)
     }
 }
 
+#[derive(Clone)]
 struct Plot {
     points: Vec<Point>,
 }
@@ -69,35 +113,144 @@ impl Plot {
         Plot { points }
     }
 
-    fn advance(&mut self) {
-        for p in &mut self.points {
-            p.advance();
+    /// Moves every point on by `seconds`, or back if negative. Returns false, leaving the
+    /// points where they are, if any would leave the `i32` plane.
+    fn advance_by(&mut self, seconds: i32) -> bool {
+        let points = self.points.iter().map(|p| p.advanced_by(seconds)).collect();
+        match points {
+            Some(points) => {
+                self.points = points;
+                true
+            }
+            None => false,
         }
     }
 
-    fn window(&self) -> Option<Window> {
-        let mut window = None;
-        for p in &self.points {
-            match &mut window {
-                None => window = Some(Window::new(p.x, p.x, p.y, p.y)),
-                Some(window) => {
-                    window.x_min = window.x_min.min(p.x);
-                    window.x_max = window.x_max.max(p.x);
-                    window.y_min = window.y_min.min(p.y);
-                    window.y_max = window.y_max.max(p.y);
+    /// Finds the second at which the bounding box of the points is smallest.
+    ///
+    /// Rather than stepping one second at a time, this jumps straight to the time at which
+    /// the points are least spread out in the least-squares sense, which has a closed form,
+    /// and then walks downhill to the smallest bounding box from there. Fails if the points
+    /// are still coming together when one would leave the `i32` plane, or if they never come
+    /// close enough together to spell anything.
+    fn converge(&self) -> Result<Convergence> {
+        let max_seconds = self
+            .points
+            .iter()
+            .map(Point::max_seconds)
+            .min()
+            .unwrap_or(0);
+        let mut seconds = self.least_spread_time().clamp(0, i64::from(max_seconds)) as i32;
+        let mut plot = self.clone();
+        plot.advance_by(seconds);
+
+        let mut area = plot.area();
+        for step in [1, -1] {
+            loop {
+                let next = match seconds.checked_add(step) {
+                    Some(next) if next < 0 => break,
+                    Some(next) if plot.advance_by(step) => next,
+                    _ => bail!(
+                        "The points are still coming together after {} seconds, when they \
+                         reach the edge of the plane",
+                        seconds
+                    ),
+                };
+                let next_area = plot.area();
+                if next_area >= area {
+                    plot.advance_by(-step);
+                    break;
                 }
+                seconds = next;
+                area = next_area;
             }
         }
 
-        window
+        if area > MAX_MESSAGE_AREA {
+            let window = plot.window().unwrap();
+            bail!(
+                "The points never come together: at their closest, after {} seconds, they \
+                 span {} by {} cells",
+                seconds,
+                window.width(),
+                window.height()
+            );
+        }
+
+        let frame = plot.to_string();
+        Ok(Convergence {
+            seconds,
+            plot,
+            frame,
+        })
     }
 
-    fn area(&self) -> i32 {
+    /// Returns the whole second nearest to when the sum of the squared distances of the
+    /// points from their centroid is smallest.
+    ///
+    /// Relative to the centroid each point is at `dp + dv t`, so the sum is a quadratic in
+    /// `t` minimised at `t = -Σ(dp · dv) / Σ|dv|²`. Points whose velocities barely differ
+    /// can put this far beyond the range of an `i32`.
+    fn least_spread_time(&self) -> i64 {
+        let n = self.points.len() as f64;
+        if n == 0.0 {
+            return 0;
+        }
+
+        let mean = |f: fn(&Point) -> i32| self.points.iter().map(|p| f(p) as f64).sum::<f64>() / n;
+        let (x, y) = (mean(|p| p.x), mean(|p| p.y));
+        let (vx, vy) = (mean(|p| p.vx), mean(|p| p.vy));
+
+        let mut numerator = 0.0;
+        let mut denominator = 0.0;
+        for p in &self.points {
+            let (dx, dy) = (p.x as f64 - x, p.y as f64 - y);
+            let (dvx, dvy) = (p.vx as f64 - vx, p.vy as f64 - vy);
+            numerator += dx * dvx + dy * dvy;
+            denominator += dvx * dvx + dvy * dvy;
+        }
+
+        // If every point moves together the spread never changes.
+        if denominator == 0.0 {
+            return 0;
+        }
+
+        (-numerator / denominator).round() as i64
+    }
+
+    fn window(&self) -> Option<Window> {
+        Window::enclosing(self.points.iter().map(|p| (p.x, p.y)))
+    }
+
+    fn area(&self) -> i64 {
         self.window().map(|w| w.area()).unwrap_or(0)
     }
+
+    /// Returns the rows of the window around the points, marking where there are points.
+    fn bitmap(&self) -> Vec<Vec<bool>> {
+        let coords = self
+            .points
+            .iter()
+            .map(|p| (p.x, p.y))
+            .collect::<HashSet<(i32, i32)>>();
+
+        let mut rows = vec![];
+        if let Some(window) = self.window() {
+            for y in window.y_min..=window.y_max {
+                rows.push(
+                    (window.x_min..=window.x_max)
+                        .map(|x| coords.contains(&(x, y)))
+                        .collect(),
+                );
+            }
+        }
+
+        rows
+    }
 }
 
-#[derive(Clone, Debug)]
+/// The smallest box containing a set of points, including the points on its edges.
+#[derive(Clone, Debug, PartialEq, Eq)]
 struct Window {
     x_min: i32,
     x_max: i32,
@@ -107,6 +260,7 @@ struct Window {
 
 impl Window {
     fn new(x_min: i32, x_max: i32, y_min: i32, y_max: i32) -> Window {
+        debug_assert!(x_min <= x_max && y_min <= y_max);
         Window {
             x_min,
             x_max,
@@ -115,40 +269,54 @@ impl Window {
         }
     }
 
-    fn width(&self) -> i32 {
-        self.x_max.abs() - self.x_min.abs()
+    /// Returns the bounding box of `points`, or `None` if there are no points.
+    fn enclosing(points: impl IntoIterator<Item = (i32, i32)>) -> Option<Window> {
+        let mut window: Option<Window> = None;
+        for (x, y) in points {
+            match &mut window {
+                None => window = Some(Window::new(x, x, y, y)),
+                Some(window) => {
+                    window.x_min = window.x_min.min(x);
+                    window.x_max = window.x_max.max(x);
+                    window.y_min = window.y_min.min(y);
+                    window.y_max = window.y_max.max(y);
+                }
+            }
+        }
+
+        window
+    }
+
+    /// The number of columns the box spans, which can exceed `i32::MAX`.
+    fn width(&self) -> i64 {
+        i64::from(self.x_max) - i64::from(self.x_min) + 1
     }
 
-    fn height(&self) -> i32 {
-        self.y_max.abs() - self.y_min.abs()
+    /// The number of rows the box spans, which can exceed `i32::MAX`.
+    fn height(&self) -> i64 {
+        i64::from(self.y_max) - i64::from(self.y_min) + 1
     }
 
-    fn area(&self) -> i32 {
-        self.width() * self.height()
+    /// The number of cells in the box. Boxes spanning almost all of the `i32` plane hold
+    /// more cells than an `i64` can count, so this saturates at `i64::MAX`.
+    fn area(&self) -> i64 {
+        self.width().saturating_mul(self.height())
     }
 }
 
 impl Display for Plot {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let coords = self
-            .points
-            .iter()
-            .map(|p| (p.x, p.y))
-            .collect::<HashSet<(i32, i32)>>();
-
         let mut out = String::from("");
-        if let Some(window) = self.window() {
-            for y in window.y_min..=window.y_max {
-                for x in window.x_min..=window.x_max {
-                    if coords.contains(&(x, y)) {
-                        out += "#";
-                    } else {
-                        out += ".";
-                    }
+        for row in self.bitmap() {
+            for lit in row {
+                if lit {
+                    out += "#";
+                } else {
+                    out += ".";
                 }
-
-                out += "\n";
             }
+
+            out += "\n";
         }
 
         f.write_str(out.as_str())?;
@@ -182,3 +350,185 @@ impl FromStr for Point {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn window(points: &[(i32, i32)]) -> Window {
+        Window::enclosing(points.iter().copied()).unwrap()
+    }
+
+    #[test]
+    fn window_of_mixed_signs() {
+        let window = window(&[(-3, 5), (4, -2), (0, 0), (-1, 1)]);
+        assert_eq!(window, Window::new(-3, 4, -2, 5));
+        assert_eq!(window.width(), 8);
+        assert_eq!(window.height(), 8);
+        assert_eq!(window.area(), 64);
+    }
+
+    #[test]
+    fn window_of_negative_points() {
+        let window = window(&[(-10, -7), (-4, -1), (-6, -3)]);
+        assert_eq!(window, Window::new(-10, -4, -7, -1));
+        assert_eq!(window.width(), 7);
+        assert_eq!(window.height(), 7);
+        assert_eq!(window.area(), 49);
+    }
+
+    #[test]
+    fn window_symmetric_about_origin() {
+        // Whatever the signs, widths must not depend on the distance from the origin.
+        for offset in -5..=5 {
+            let window = window(&[(offset - 2, offset - 3), (offset + 2, offset + 3)]);
+            assert_eq!(
+                (window.width(), window.height()),
+                (5, 7),
+                "offset {}",
+                offset
+            );
+        }
+    }
+
+    #[test]
+    fn window_of_single_point() {
+        let window = window(&[(-5, 3)]);
+        assert_eq!((window.width(), window.height(), window.area()), (1, 1, 1));
+        assert_eq!(Window::enclosing(vec![]), None);
+    }
+
+    #[test]
+    fn window_of_extreme_points() {
+        let window = window(&[(i32::MIN, 0), (i32::MAX, 1)]);
+        assert_eq!(window.width(), 1 << 32);
+        assert_eq!(window.area(), 1 << 33);
+
+        let plane = Window::new(i32::MIN, i32::MAX, i32::MIN, i32::MAX);
+        assert_eq!(plane.area(), i64::MAX);
+    }
+
+    #[test]
+    fn reads_example_message() {
+        let points = fs::read_to_string("input/sample.txt")
+            .unwrap()
+            .lines()
+            .map(|line| line.parse().unwrap())
+            .collect();
+        let convergence = Plot::new(points).converge().unwrap();
+        let message = ocr::read(&convergence.plot.bitmap());
+        assert_eq!(message.text, "HI");
+        assert!(message.unknown.is_empty());
+    }
+
+    #[test]
+    fn reports_unknown_glyphs() {
+        let bitmap: Vec<Vec<bool>> = ["#....#...#.#", "######....#.", "#....#...#.#"]
+            .iter()
+            .map(|row| row.chars().map(|c| c == '#').collect())
+            .collect();
+        let message = ocr::read(&bitmap);
+        assert_eq!(message.text, "??");
+        assert_eq!(message.unknown[1].column, 9);
+        assert_eq!(message.unknown[1].rows, vec!["#.#", ".#.", "#.#"]);
+    }
+
+    #[test]
+    fn fails_to_converge_beyond_the_plane() {
+        // These points would meet after 1000 seconds, but the first goes over the edge of
+        // the plane after 483.
+        let plot = Plot::new(vec![
+            Point {
+                x: 2_147_000_000,
+                y: 0,
+                vx: 1000,
+                vy: 0,
+            },
+            Point {
+                x: 2_147_001_000,
+                y: 0,
+                vx: 999,
+                vy: 0,
+            },
+        ]);
+        assert_eq!(plot.least_spread_time(), 1000);
+
+        assert_eq!(
+            plot.converge().err().unwrap().to_string(),
+            "The points are still coming together after 483 seconds, when they reach the \
+             edge of the plane"
+        );
+
+        let mut edge = plot.clone();
+        assert!(edge.advance_by(483));
+        assert!(!edge.advance_by(1));
+        assert_eq!(edge.points[0].x, 2_147_483_000);
+        assert_eq!(edge.area(), 518);
+    }
+
+    #[test]
+    fn fails_to_converge_when_moving_together() {
+        let plot = Plot::new(vec![
+            Point {
+                x: 0,
+                y: 0,
+                vx: 3,
+                vy: -2,
+            },
+            Point {
+                x: 100_000,
+                y: 0,
+                vx: 3,
+                vy: -2,
+            },
+        ]);
+        assert_eq!(
+            plot.converge().err().unwrap().to_string(),
+            "The points never come together: at their closest, after 0 seconds, they span \
+             100001 by 1 cells"
+        );
+    }
+
+    #[test]
+    fn converges_around_origin() {
+        // A plus sign centred on the origin, reached after 7 seconds by points flying in
+        // from every side of it.
+        let targets = [
+            (0, 0),
+            (-2, 0),
+            (-1, 0),
+            (1, 0),
+            (2, 0),
+            (0, -2),
+            (0, -1),
+            (0, 1),
+            (0, 2),
+        ];
+        let velocities = [
+            (1, 1),
+            (3, -2),
+            (-1, 2),
+            (-3, 1),
+            (2, 3),
+            (-2, -3),
+            (1, -1),
+            (0, 2),
+            (-1, 0),
+        ];
+        let points = targets
+            .iter()
+            .zip(&velocities)
+            .map(|((x, y), (vx, vy))| Point {
+                x: x - 7 * vx,
+                y: y - 7 * vy,
+                vx: *vx,
+                vy: *vy,
+            })
+            .collect();
+
+        let convergence = Plot::new(points).converge().unwrap();
+        assert_eq!(convergence.seconds, 7);
+        assert_eq!(convergence.frame, "..#..\n..#..\n#####\n..#..\n..#..\n");
+    }
+}
@@ -0,0 +1,313 @@
+//! Recognition of the letters spelt out by the stars, in the font the puzzle uses.
+
+/// The letters of the 6×10 font used by puzzle inputs, each drawn as rows of pixels.
+const FONT: &[(char, &str)] = &[
+    (
+        'A',
+        "\
+..##..
+.#..#.
+#....#
+#....#
+#....#
+######
+#....#
+#....#
+#....#
+#....#",
+    ),
+    (
+        'B',
+        "\
+#####.
+#....#
+#....#
+#....#
+#####.
+#....#
+#....#
+#....#
+#....#
+#####.",
+    ),
+    (
+        'C',
+        "\
+.####.
+#....#
+#.....
+#.....
+#.....
+#.....
+#.....
+#.....
+#....#
+.####.",
+    ),
+    (
+        'E',
+        "\
+######
+#.....
+#.....
+#.....
+#####.
+#.....
+#.....
+#.....
+#.....
+######",
+    ),
+    (
+        'F',
+        "\
+######
+#.....
+#.....
+#.....
+#####.
+#.....
+#.....
+#.....
+#.....
+#.....",
+    ),
+    (
+        'G',
+        "\
+.####.
+#....#
+#.....
+#.....
+#.....
+#..###
+#....#
+#....#
+#...##
+.###.#",
+    ),
+    (
+        'H',
+        "\
+#....#
+#....#
+#....#
+#....#
+######
+#....#
+#....#
+#....#
+#....#
+#....#",
+    ),
+    (
+        'J',
+        "\
+...###
+....#.
+....#.
+....#.
+....#.
+....#.
+....#.
+#...#.
+#...#.
+.###..",
+    ),
+    (
+        'K',
+        "\
+#....#
+#...#.
+#..#..
+#.#...
+##....
+##....
+#.#...
+#..#..
+#...#.
+#....#",
+    ),
+    (
+        'L',
+        "\
+#.....
+#.....
+#.....
+#.....
+#.....
+#.....
+#.....
+#.....
+#.....
+######",
+    ),
+    (
+        'N',
+        "\
+#....#
+##...#
+##...#
+#.#..#
+#.#..#
+#..#.#
+#..#.#
+#...##
+#...##
+#....#",
+    ),
+    (
+        'P',
+        "\
+#####.
+#....#
+#....#
+#....#
+#####.
+#.....
+#.....
+#.....
+#.....
+#.....",
+    ),
+    (
+        'R',
+        "\
+#####.
+#....#
+#....#
+#....#
+#####.
+#..#..
+#...#.
+#...#.
+#....#
+#....#",
+    ),
+    (
+        'X',
+        "\
+#....#
+#....#
+.#..#.
+.#..#.
+..##..
+..##..
+.#..#.
+.#..#.
+#....#
+#....#",
+    ),
+    (
+        'Z',
+        "\
+######
+.....#
+.....#
+....#.
+...#..
+..#...
+.#....
+#.....
+#.....
+######",
+    ),
+    // The smaller letters of the puzzle's example.
+    (
+        'H',
+        "\
+#...#
+#...#
+#...#
+#####
+#...#
+#...#
+#...#
+#...#",
+    ),
+    (
+        'I',
+        "\
+###
+.#.
+.#.
+.#.
+.#.
+.#.
+.#.
+###",
+    ),
+];
+
+/// A run of adjacent columns containing lit pixels, separated from the next by at least
+/// one blank column.
+#[derive(Debug)]
+pub struct Glyph {
+    /// The column the glyph starts at, counting from the left of the message.
+    pub column: usize,
+    pub rows: Vec<String>,
+}
+
+/// The text read from a bitmap, with `?` standing in for any glyph not in the font.
+#[derive(Debug)]
+pub struct Message {
+    pub text: String,
+    pub unknown: Vec<Glyph>,
+}
+
+/// Splits `bitmap` (a set of rows of lit or unlit pixels) into glyphs at blank columns and
+/// reads each as a letter of the font.
+pub fn read(bitmap: &[Vec<bool>]) -> Message {
+    let width = bitmap.iter().map(|row| row.len()).max().unwrap_or(0);
+    let lit = |x: usize| bitmap.iter().any(|row| row.get(x) == Some(&true));
+
+    let mut message = Message {
+        text: String::new(),
+        unknown: vec![],
+    };
+    let mut x = 0;
+    while x < width {
+        if !lit(x) {
+            x += 1;
+            continue;
+        }
+
+        let start = x;
+        while x < width && lit(x) {
+            x += 1;
+        }
+        let glyph = Glyph {
+            column: start,
+            rows: trim_rows(bitmap.iter().map(|row| {
+                (start..x)
+                    .map(|x| if row.get(x) == Some(&true) { '#' } else { '.' })
+                    .collect()
+            })),
+        };
+
+        match recognise(&glyph) {
+            Some(letter) => message.text.push(letter),
+            None => {
+                message.text.push('?');
+                message.unknown.push(glyph);
+            }
+        }
+    }
+
+    message
+}
+
+fn recognise(glyph: &Glyph) -> Option<char> {
+    FONT.iter()
+        .find(|(_, pixels)| trim_rows(pixels.lines().map(|row| row.to_string())) == glyph.rows)
+        .map(|(letter, _)| *letter)
+}
+
+/// Drops any blank rows above and below a glyph.
+fn trim_rows(rows: impl Iterator<Item = String>) -> Vec<String> {
+    let mut rows: Vec<String> = rows.skip_while(|row| !row.contains('#')).collect();
+    while rows.last().is_some_and(|row| !row.contains('#')) {
+        rows.pop();
+    }
+    rows
+}
@@ -0,0 +1,329 @@
+//! An interactive terminal viewer for scrubbing backwards and forwards through time.
+
+use super::{Plot, Window};
+use anyhow::{Context, Result};
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    process::{Command, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+const CLEAR: &str = "\x1b[2J\x1b[H";
+const HIDE_CURSOR: &str = "\x1b[?25l";
+const SHOW_CURSOR: &str = "\x1b[?25h";
+
+const DEFAULT_DELAY_MS: u64 = 300;
+const MIN_DELAY_MS: u64 = 10;
+const MAX_DELAY_MS: u64 = 2560;
+
+/// The lines at the bottom of the terminal kept for the status and help text.
+const STATUS_LINES: usize = 2;
+
+/// How many seconds before the convergence the viewer starts at.
+const LEAD_IN_SECONDS: i32 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Key {
+    PlayPause,
+    StepForward,
+    StepBack,
+    Faster,
+    Slower,
+    Reverse,
+    Converge,
+    Quit,
+}
+
+/// Puts the controlling terminal into unbuffered, silent input mode for as long as it
+/// lives, so single key presses can be read as they happen. Ctrl-C arrives as a key
+/// rather than killing the process, so that the terminal is always put back as it was.
+/// Reads give up after a tenth of a second without input, so a lone Esc can be told apart
+/// from the start of an escape sequence.
+struct RawTerminal {
+    /// The terminal's settings beforehand, as given by `stty -g`.
+    saved: String,
+}
+
+impl RawTerminal {
+    fn enable() -> Result<RawTerminal> {
+        let saved = stty(&["-g"])?.trim().to_string();
+        stty(&["-icanon", "-echo", "-isig", "min", "0", "time", "1"])?;
+        print!("{}", HIDE_CURSOR);
+        Ok(RawTerminal { saved })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+        print!("{}", SHOW_CURSOR);
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Runs `stty` against the controlling terminal, since stdin holds the puzzle input.
+fn stty(args: &[&str]) -> Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(File::open("/dev/tty").context("No terminal to view the stars on")?)
+        .stderr(Stdio::inherit())
+        .output()
+        .context("Failed to run stty")?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Returns the number of rows and columns in the terminal.
+fn terminal_size() -> (usize, usize) {
+    let size = stty(&["size"]).unwrap_or_default();
+    let mut dims = size.split_whitespace().filter_map(|d| d.parse().ok());
+    match (dims.next(), dims.next()) {
+        (Some(rows), Some(cols)) => (rows, cols),
+        _ => (24, 80),
+    }
+}
+
+/// Reads the next byte from the terminal, or `None` if none arrives before the read
+/// times out.
+fn read_byte(tty: &mut File) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    Ok((tty.read(&mut byte)? == 1).then_some(byte[0]))
+}
+
+/// Reads key presses from the terminal on a background thread.
+fn read_keys() -> Result<Receiver<Key>> {
+    let mut tty = File::open("/dev/tty").context("No terminal to read keys from")?;
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        loop {
+            let byte = match read_byte(&mut tty) {
+                Ok(Some(byte)) => byte,
+                Ok(None) => continue,
+                Err(_) => break,
+            };
+            let key = match byte {
+                b' ' | b'p' => Key::PlayPause,
+                b'>' | b'.' | b'l' => Key::StepForward,
+                b'<' | b',' | b'h' => Key::StepBack,
+                b'+' | b'=' => Key::Faster,
+                b'-' | b'_' => Key::Slower,
+                b'r' => Key::Reverse,
+                b'c' => Key::Converge,
+                // Ctrl-C arrives as a byte like any other while the terminal is raw.
+                b'q' | 0x03 => Key::Quit,
+                // Arrow keys arrive as escape sequences such as `ESC [ C`, whereas Esc on
+                // its own has nothing following it.
+                0x1b => match read_byte(&mut tty).ok().flatten() {
+                    None => Key::Quit,
+                    Some(b'[') => match read_byte(&mut tty).ok().flatten() {
+                        Some(b'C') => Key::StepForward,
+                        Some(b'D') => Key::StepBack,
+                        Some(b'A') => Key::Faster,
+                        Some(b'B') => Key::Slower,
+                        _ => continue,
+                    },
+                    Some(_) => continue,
+                },
+                _ => continue,
+            };
+            if sender.send(key).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+/// Lets the user play, pause and step through the motion of the stars either way in
+/// time, starting shortly before they converge on the message at `convergence` seconds.
+pub fn view(plot: &Plot, convergence: i32) -> Result<()> {
+    let mut viewer = Viewer {
+        plot: plot.clone(),
+        seconds: 0,
+        convergence,
+        playing: true,
+        direction: 1,
+        delay: Duration::from_millis(DEFAULT_DELAY_MS),
+    };
+    viewer.seek((convergence - LEAD_IN_SECONDS).max(0));
+
+    let keys = read_keys()?;
+    let _terminal = RawTerminal::enable()?;
+    let mut stdout = io::stdout();
+    // Asking stty for the size means starting a process, so rather than doing so for every
+    // frame the size is only checked again when a key is pressed.
+    let (mut rows, mut cols) = terminal_size();
+    loop {
+        write!(stdout, "{}{}", CLEAR, viewer.render(rows, cols))?;
+        stdout.flush()?;
+
+        let key = if viewer.playing {
+            match keys.recv_timeout(viewer.delay) {
+                Ok(key) => Some(key),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        } else {
+            match keys.recv() {
+                Ok(key) => Some(key),
+                Err(_) => break,
+            }
+        };
+
+        match key {
+            None => viewer.step(viewer.direction),
+            Some(Key::Quit) => break,
+            Some(key) => {
+                viewer.handle(key);
+                (rows, cols) = terminal_size();
+            }
+        }
+    }
+
+    write!(stdout, "{}", CLEAR)?;
+    Ok(())
+}
+
+struct Viewer {
+    plot: Plot,
+    seconds: i32,
+    convergence: i32,
+    playing: bool,
+    /// Which way time runs while playing: 1 for forwards or -1 for backwards.
+    direction: i32,
+    /// How long each second of the simulation is shown for while playing.
+    delay: Duration,
+}
+
+impl Viewer {
+    fn handle(&mut self, key: Key) {
+        match key {
+            Key::PlayPause => self.playing = !self.playing,
+            Key::StepForward => {
+                self.playing = false;
+                self.step(1);
+            }
+            Key::StepBack => {
+                self.playing = false;
+                self.step(-1);
+            }
+            Key::Faster => self.delay = (self.delay / 2).max(Duration::from_millis(MIN_DELAY_MS)),
+            Key::Slower => self.delay = (self.delay * 2).min(Duration::from_millis(MAX_DELAY_MS)),
+            Key::Reverse => self.direction = -self.direction,
+            Key::Converge => {
+                self.playing = false;
+                self.seek(self.convergence);
+            }
+            Key::Quit => {}
+        }
+    }
+
+    /// Moves `seconds` forwards, or backwards if negative, by applying the velocities.
+    /// Does nothing if that would take a star off the edge of the plane.
+    fn step(&mut self, seconds: i32) {
+        if let Some(total) = self.seconds.checked_add(seconds) {
+            if self.plot.advance_by(seconds) {
+                self.seconds = total;
+            }
+        }
+    }
+
+    fn seek(&mut self, seconds: i32) {
+        self.step(seconds - self.seconds);
+    }
+
+    /// Draws the stars scaled to fit within the terminal, followed by the status lines.
+    fn render(&self, rows: usize, cols: usize) -> String {
+        let height = rows.saturating_sub(STATUS_LINES).max(1);
+        let (frame, scale) = match self.plot.window() {
+            Some(window) => draw(&self.plot, &window, height, cols.max(1)),
+            None => (String::new(), 1),
+        };
+
+        format!(
+            "{}{} {:>6}s  {}  {:.0} fps  area {}  1:{}\n\
+             [space] play/pause  [</>] step  [+/-] speed  [r] reverse  [c] converge  [q/esc] quit",
+            frame,
+            if self.seconds == self.convergence {
+                "*"
+            } else {
+                " "
+            },
+            self.seconds,
+            match (self.playing, self.direction) {
+                (false, _) => "paused   ",
+                (true, 1) => "playing >",
+                (true, _) => "playing <",
+            },
+            1.0 / self.delay.as_secs_f64(),
+            self.plot.area(),
+            scale
+        )
+    }
+}
+
+/// Draws `plot` with `window` fitted into at most `rows` by `cols` characters. Each
+/// character covers a square of `scale` by `scale` cells, marked if it holds any star, so
+/// that the view zooms in as the stars come together. Returns the drawing and its scale.
+fn draw(plot: &Plot, window: &Window, rows: usize, cols: usize) -> (String, i64) {
+    let scale = |cells: i64, chars: usize| (cells + chars as i64 - 1) / chars as i64;
+    let scale = scale(window.width(), cols)
+        .max(scale(window.height(), rows))
+        .max(1);
+    let width = ((window.width() + scale - 1) / scale) as usize;
+    let height = ((window.height() + scale - 1) / scale) as usize;
+
+    let mut grid = vec![vec!['.'; width]; height];
+    for p in &plot.points {
+        let x = (i64::from(p.x) - i64::from(window.x_min)) / scale;
+        let y = (i64::from(p.y) - i64::from(window.y_min)) / scale;
+        grid[y as usize][x as usize] = '#';
+    }
+
+    let mut frame = String::new();
+    for row in grid {
+        frame.extend(row);
+        frame.push('\n');
+    }
+
+    (frame, scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Point;
+
+    fn plot(points: &[(i32, i32)]) -> Plot {
+        Plot::new(
+            points
+                .iter()
+                .map(|(x, y)| Point {
+                    x: *x,
+                    y: *y,
+                    vx: 0,
+                    vy: 0,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn draws_small_windows_unscaled() {
+        let plot = plot(&[(-1, -1), (1, 0)]);
+        let (frame, scale) = draw(&plot, &plot.window().unwrap(), 10, 10);
+        assert_eq!(scale, 1);
+        assert_eq!(frame, "#..\n..#\n");
+    }
+
+    #[test]
+    fn scales_large_windows_to_fit() {
+        let plot = plot(&[(-50, -50), (49, 49), (0, 0)]);
+        let (frame, scale) = draw(&plot, &plot.window().unwrap(), 4, 10);
+        assert_eq!(scale, 25);
+        assert_eq!(frame, "#...\n....\n..#.\n...#\n");
+    }
+}
@@ -1,21 +1,23 @@
 use anyhow::Result;
-use std::io::{self, Read};
+use std::{
+    io::{self, Read},
+    thread,
+};
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
 
     let serial = input.trim().parse::<usize>()?;
     let fuel_grid = FuelGrid::new(300, 300, serial);
 
-    part1(fuel_grid.clone()).await;
-    part2(fuel_grid.clone()).await;
+    part1(&fuel_grid);
+    part2(&fuel_grid);
 
     Ok(())
 }
 
-async fn part1(fuel_grid: FuelGrid) {
+fn part1(fuel_grid: &FuelGrid) {
     let cell_group = find_best_cell_group(fuel_grid, 3).unwrap();
     println!(
         "Part 1: Max power is {} at ({}, {})",
@@ -23,50 +25,93 @@ async fn part1(fuel_grid: FuelGrid) {
     );
 }
 
-async fn part2(fuel_grid: FuelGrid) {
-    let mut handles = vec![];
-    for size in 1..=fuel_grid.width {
-        let fuel_grid = fuel_grid.clone();
-        let handle = tokio::task::spawn(async move {
-            find_best_cell_group(fuel_grid, size)
-        });
-        handles.push(handle);
-    }
-
-    let mut results = vec![];
-    for handle in handles {
-        let result = handle.await.unwrap().unwrap();
-        results.push(result);
-    }
-
-    let cell_group = results.iter().max_by_key(|g| g.total_power).unwrap();
-
+fn part2(fuel_grid: &FuelGrid) {
+    let cell_group = find_best_square_cell_group(fuel_grid);
     println!(
         "Part 2: Max power is {} at ({}, {}) using group size {}",
         cell_group.total_power, cell_group.x, cell_group.y, cell_group.width,
     );
 }
 
-fn find_best_cell_group(fuel_grid: FuelGrid, size: usize) -> Option<FuelCellGroup> {
+/// Finds the square group of any size with the most power, preferring larger groups when
+/// several have the same power.
+fn find_best_square_cell_group(fuel_grid: &FuelGrid) -> FuelCellGroup {
+    let max_size = fuel_grid.width.min(fuel_grid.height);
+    let threads = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(max_size);
+
+    // Smaller sizes have more groups to check, so deal the sizes out in turn to balance
+    // the work between threads.
+    let mut results: Vec<FuelCellGroup> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|first| {
+                scope.spawn(move || {
+                    (first + 1..=max_size)
+                        .step_by(threads)
+                        .filter_map(|size| find_best_cell_group(fuel_grid, size))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    results.sort_by_key(|g| g.width);
+
+    results.into_iter().max_by_key(|g| g.total_power).unwrap()
+}
+
+fn find_best_cell_group(fuel_grid: &FuelGrid, size: usize) -> Option<FuelCellGroup> {
     fuel_grid
         .cell_groups(size, size)
         .max_by_key(|g| g.total_power)
 }
 
-#[derive(Clone)]
 struct FuelGrid {
     width: usize,
     height: usize,
     serial: usize,
+    /// A summed-area table, holding the total power of every cell above and to the left of
+    /// each cell, inclusive. It has an extra row and column of zeros at the top and left.
+    sums: Vec<i32>,
 }
 
 impl FuelGrid {
     fn new(width: usize, height: usize, serial: usize) -> FuelGrid {
-        FuelGrid {
+        let mut fuel_grid = FuelGrid {
             width,
             height,
             serial,
+            sums: vec![0; (width + 1) * (height + 1)],
+        };
+
+        for y in 1..=height {
+            for x in 1..=width {
+                let power = fuel_grid.cell_power(x, y).unwrap();
+                fuel_grid.sums[y * (width + 1) + x] =
+                    power + fuel_grid.sum_to(x - 1, y) + fuel_grid.sum_to(x, y - 1)
+                        - fuel_grid.sum_to(x - 1, y - 1);
+            }
         }
+
+        fuel_grid
+    }
+
+    /// The total power of the cells from (1, 1) to (x, y) inclusive, or 0 if x or y is 0.
+    fn sum_to(&self, x: usize, y: usize) -> i32 {
+        self.sums[y * (self.width + 1) + x]
+    }
+
+    /// The total power of the `width` × `height` group with its top-left cell at (x, y),
+    /// which must lie within the grid.
+    fn group_power(&self, x: usize, y: usize, width: usize, height: usize) -> i32 {
+        let (x2, y2) = (x + width - 1, y + height - 1);
+        self.sum_to(x2, y2) - self.sum_to(x - 1, y2) - self.sum_to(x2, y - 1)
+            + self.sum_to(x - 1, y - 1)
     }
 
     fn cell_power(&self, x: usize, y: usize) -> Option<i32> {
@@ -81,7 +126,7 @@ impl FuelGrid {
         Some(power)
     }
 
-    fn cell_groups(&self, group_width: usize, group_height: usize) -> FuelGridIter {
+    fn cell_groups(&self, group_width: usize, group_height: usize) -> FuelGridIter<'_> {
         FuelGridIter::new(self, group_width, group_height)
     }
 }
@@ -94,8 +139,8 @@ struct FuelGridIter<'a> {
     curr_y: usize,
 }
 
-impl<'a> FuelGridIter<'a> {
-    fn new(fuel_grid: &FuelGrid, width: usize, height: usize) -> FuelGridIter {
+impl FuelGridIter<'_> {
+    fn new(fuel_grid: &FuelGrid, width: usize, height: usize) -> FuelGridIter<'_> {
         FuelGridIter {
             fuel_grid,
             width,
@@ -106,7 +151,7 @@ impl<'a> FuelGridIter<'a> {
     }
 }
 
-impl<'a> Iterator for FuelGridIter<'a> {
+impl Iterator for FuelGridIter<'_> {
     type Item = FuelCellGroup;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -114,19 +159,16 @@ impl<'a> Iterator for FuelGridIter<'a> {
             return None;
         }
 
-        let mut cell_group_power = 0;
-        for x in self.curr_x..self.curr_x + self.width {
-            for y in self.curr_y..self.curr_y + self.height {
-                cell_group_power += self.fuel_grid.cell_power(x, y).unwrap();
-            }
-        }
-
         let cell_group = FuelCellGroup {
             x: self.curr_x,
             y: self.curr_y,
             width: self.width,
-            height: self.height,
-            total_power: cell_group_power,
+            total_power: self.fuel_grid.group_power(
+                self.curr_x,
+                self.curr_y,
+                self.width,
+                self.height,
+            ),
         };
 
         self.curr_x += 1;
@@ -143,6 +185,100 @@ struct FuelCellGroup {
     x: usize,
     y: usize,
     width: usize,
-    height: usize,
     total_power: i32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn best(cell_group: FuelCellGroup) -> (usize, usize, usize, i32) {
+        (
+            cell_group.x,
+            cell_group.y,
+            cell_group.width,
+            cell_group.total_power,
+        )
+    }
+
+    #[test]
+    fn cell_power() {
+        for (serial, x, y, power) in [
+            (8, 3, 5, 4),
+            (57, 122, 79, -5),
+            (39, 217, 196, 0),
+            (71, 101, 153, 4),
+        ] {
+            let fuel_grid = FuelGrid::new(300, 300, serial);
+            assert_eq!(fuel_grid.cell_power(x, y), Some(power), "serial {}", serial);
+        }
+
+        let fuel_grid = FuelGrid::new(300, 300, 18);
+        assert_eq!(fuel_grid.cell_power(0, 1), None);
+        assert_eq!(fuel_grid.cell_power(301, 1), None);
+        assert_eq!(fuel_grid.cell_power(1, 301), None);
+    }
+
+    #[test]
+    fn best_groups_of_three() {
+        let fuel_grid = FuelGrid::new(300, 300, 18);
+        assert_eq!(
+            best(find_best_cell_group(&fuel_grid, 3).unwrap()),
+            (33, 45, 3, 29)
+        );
+
+        let fuel_grid = FuelGrid::new(300, 300, 42);
+        assert_eq!(
+            best(find_best_cell_group(&fuel_grid, 3).unwrap()),
+            (21, 61, 3, 30)
+        );
+    }
+
+    #[test]
+    fn best_groups_of_any_size() {
+        let fuel_grid = FuelGrid::new(300, 300, 18);
+        assert_eq!(
+            best(find_best_square_cell_group(&fuel_grid)),
+            (90, 269, 16, 113)
+        );
+
+        let fuel_grid = FuelGrid::new(300, 300, 42);
+        assert_eq!(
+            best(find_best_square_cell_group(&fuel_grid)),
+            (232, 251, 12, 119)
+        );
+    }
+
+    #[test]
+    fn group_power_matches_brute_force() {
+        let (width, height) = (11, 7);
+        let fuel_grid = FuelGrid::new(width, height, 18);
+        for y in 1..=height {
+            for x in 1..=width {
+                for group_height in 1..=height - y + 1 {
+                    for group_width in 1..=width - x + 1 {
+                        let mut power = 0;
+                        for cy in y..y + group_height {
+                            for cx in x..x + group_width {
+                                power += fuel_grid.cell_power(cx, cy).unwrap();
+                            }
+                        }
+                        assert_eq!(
+                            fuel_grid.group_power(x, y, group_width, group_height),
+                            power,
+                            "{}x{} group at ({}, {})",
+                            group_width,
+                            group_height,
+                            x,
+                            y
+                        );
+                    }
+                }
+            }
+        }
+
+        // Every group is visited once, in reading order.
+        let groups: Vec<_> = fuel_grid.cell_groups(10, 6).map(|g| (g.x, g.y)).collect();
+        assert_eq!(groups, vec![(1, 1), (2, 1), (1, 2), (2, 2)]);
+    }
+}